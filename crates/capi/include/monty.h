@@ -0,0 +1,160 @@
+/*
+ * C API for embedding Monty, implemented in crates/capi/src/lib.rs.
+ * Regenerate with `cbindgen --config cbindgen.toml --output include/monty.h`
+ * from crates/capi after changing it.
+ */
+
+#ifndef MONTY_H
+#define MONTY_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+#define MONTY_OK 0
+
+#define MONTY_ERROR -1
+
+typedef struct MontyEngine MontyEngine;
+
+typedef struct MontyLimits {
+  /**
+   * Maximum nodes, or 0 for none.
+   */
+  int64_t nodes;
+  /**
+   * Maximum time in milliseconds, or 0 for none.
+   */
+  int64_t movetime;
+  /**
+   * Maximum depth, or 0 for none.
+   */
+  int32_t depth;
+  /**
+   * Clock of white and black in milliseconds, or 0 for none.
+   */
+  int64_t time[2];
+  /**
+   * Increment of white and black in milliseconds, or 0 for none.
+   */
+  int64_t inc[2];
+  /**
+   * Moves until the next time control, or 0 for none.
+   */
+  int32_t movestogo;
+} MontyLimits;
+
+typedef struct MontyResult {
+  /**
+   * Move to play in UCI notation, null terminated.
+   */
+  char best_move[8];
+  /**
+   * Score in centipawns, if not a mate score.
+   */
+  int32_t score_cp;
+  /**
+   * Moves until mate, negative if getting mated, or 0 for none.
+   */
+  int32_t mate;
+  float win;
+  float draw;
+  float loss;
+  uint64_t nodes;
+} MontyResult;
+
+typedef struct MontyEval {
+  int32_t cp;
+  float win;
+  float draw;
+  float loss;
+} MontyEval;
+
+typedef struct MontyMovePolicy {
+  /**
+   * Move in UCI notation, null terminated.
+   */
+  char mov[8];
+  float probability;
+} MontyMovePolicy;
+
+/**
+ * Receives every line of search output, with the `user_data` it was
+ * registered with.
+ */
+typedef void (*MontyCallback)(const char *line, void *user_data);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Creates an engine with the networks at the given paths, where null
+ * selects the default file names. Returns null if either is missing
+ * or has the wrong size.
+ */
+MontyEngine *monty_engine_new(const char *policy_path, const char *value_path);
+
+/**
+ * Creates an engine with the networks embedded in the library, or
+ * returns null if it was built without them.
+ */
+MontyEngine *monty_engine_new_embedded(void);
+
+/**
+ * Frees an engine, after stopping any search it is running.
+ */
+void monty_engine_free(MontyEngine *engine);
+
+/**
+ * Sends every line of search output to `callback`, which is called from
+ * the search thread, or stops doing so if it is null. The callback may
+ * call this to replace or remove itself.
+ */
+void monty_engine_set_callback(MontyEngine *engine, MontyCallback callback, void *user_data);
+
+/**
+ * Sets an option by its UCI name, where `value` may be null for buttons.
+ */
+int32_t monty_engine_set_option(MontyEngine *engine, const char *name, const char *value);
+
+/**
+ * Sets the position to `fen`, or the starting position if null, followed
+ * by space separated `moves` in UCI notation, if not null. Fails on an
+ * invalid FEN or illegal move, leaving the position unchanged.
+ */
+int32_t monty_engine_set_position(MontyEngine *engine, const char *fen, const char *moves);
+
+/**
+ * Searches the current position, and fills `result` if not null. Fails
+ * if `limits` has no limit set, as the search would never finish.
+ */
+int32_t monty_engine_search(MontyEngine *engine, const MontyLimits *limits, MontyResult *result);
+
+/**
+ * Writes the space separated principal variation of the last search to
+ * `buf`, truncated to `len - 1` characters and null terminated, and
+ * returns its full length.
+ */
+size_t monty_engine_pv(MontyEngine *engine, char *buf, size_t len);
+
+/**
+ * Evaluates the current position with the value network, from the
+ * perspective of the side to move.
+ */
+int32_t monty_engine_evaluate(MontyEngine *engine, MontyEval *eval);
+
+/**
+ * Writes up to `len` legal moves of the current position, with the
+ * probability the policy network gives them and from most to least
+ * likely, to `moves`, and returns the number of legal moves.
+ */
+size_t monty_engine_policy(MontyEngine *engine, MontyMovePolicy *moves, size_t len);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* MONTY_H */
@@ -0,0 +1,427 @@
+//! C API for embedding Monty, see `include/monty.h` for the documentation
+//! of every function. All functions are safe to call with null pointers,
+//! which they treat as errors.
+
+use std::{
+    ffi::{c_char, c_void, CStr, CString},
+    ptr,
+    sync::{Arc, Mutex},
+};
+
+use monty::{
+    chess::ChessState,
+    engine::{Engine, GoOptions},
+    mcts::{Score, SearchEvent},
+    networks::{PolicyFileDefaultName, PolicyNetwork, ValueFileDefaultName, ValueNetwork},
+    read_into_struct_unchecked, MappedWeights,
+};
+
+pub const MONTY_OK: i32 = 0;
+pub const MONTY_ERROR: i32 = -1;
+
+/// Receives every line of search output, with the `user_data` it was
+/// registered with.
+pub type MontyCallback = Option<unsafe extern "C" fn(line: *const c_char, user_data: *mut c_void)>;
+
+#[derive(Clone, Copy)]
+struct Callback {
+    function: unsafe extern "C" fn(*const c_char, *mut c_void),
+    user_data: *mut c_void,
+}
+
+// the caller is responsible for `user_data` being usable from the search thread
+unsafe impl Send for Callback {}
+
+pub struct MontyEngine {
+    // declared first to be dropped before the networks it borrows
+    engine: Engine,
+    callback: Arc<Mutex<Option<Callback>>>,
+    _networks: Option<(
+        MappedWeights<'static, PolicyNetwork>,
+        MappedWeights<'static, ValueNetwork>,
+    )>,
+}
+
+#[repr(C)]
+pub struct MontyLimits {
+    /// Maximum nodes, or 0 for none.
+    pub nodes: i64,
+    /// Maximum time in milliseconds, or 0 for none.
+    pub movetime: i64,
+    /// Maximum depth, or 0 for none.
+    pub depth: i32,
+    /// Clock of white and black in milliseconds, or 0 for none.
+    pub time: [i64; 2],
+    /// Increment of white and black in milliseconds, or 0 for none.
+    pub inc: [i64; 2],
+    /// Moves until the next time control, or 0 for none.
+    pub movestogo: i32,
+}
+
+#[repr(C)]
+pub struct MontyResult {
+    /// Move to play in UCI notation, null terminated.
+    pub best_move: [c_char; 8],
+    /// Score in centipawns, if not a mate score.
+    pub score_cp: i32,
+    /// Moves until mate, negative if getting mated, or 0 for none.
+    pub mate: i32,
+    pub win: f32,
+    pub draw: f32,
+    pub loss: f32,
+    pub nodes: u64,
+}
+
+#[repr(C)]
+pub struct MontyEval {
+    pub cp: i32,
+    pub win: f32,
+    pub draw: f32,
+    pub loss: f32,
+}
+
+#[repr(C)]
+pub struct MontyMovePolicy {
+    /// Move in UCI notation, null terminated.
+    pub mov: [c_char; 8],
+    pub probability: f32,
+}
+
+impl MontyEngine {
+    fn new(
+        policy: &'static PolicyNetwork,
+        value: &'static ValueNetwork,
+        networks: Option<(
+            MappedWeights<'static, PolicyNetwork>,
+            MappedWeights<'static, ValueNetwork>,
+        )>,
+    ) -> *mut Self {
+        let callback = Arc::new(Mutex::new(None::<Callback>));
+        let reporter_callback = callback.clone();
+
+        let engine = Engine::new(policy, value, move |event: SearchEvent| {
+            // copied out so the callback can replace or remove itself
+            let callback = *reporter_callback.lock().unwrap();
+
+            if let Some(callback) = callback {
+                if let Ok(line) = CString::new(event.to_string()) {
+                    unsafe { (callback.function)(line.as_ptr(), callback.user_data) };
+                }
+            }
+        });
+
+        Box::into_raw(Box::new(Self {
+            engine,
+            callback,
+            _networks: networks,
+        }))
+    }
+}
+
+fn map_network<T>(path: *const c_char, default: &str) -> Option<MappedWeights<'static, T>> {
+    let path = if path.is_null() {
+        default
+    } else {
+        unsafe { CStr::from_ptr(path) }.to_str().ok()?
+    };
+
+    let size = std::fs::metadata(path).ok()?.len();
+
+    if size != std::mem::size_of::<T>() as u64 {
+        return None;
+    }
+
+    Some(unsafe { read_into_struct_unchecked(path) })
+}
+
+fn c_str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        None
+    } else {
+        unsafe { CStr::from_ptr(s) }.to_str().ok()
+    }
+}
+
+fn write_str(buf: &mut [c_char; 8], s: &str) {
+    *buf = [0; 8];
+
+    for (dst, &src) in buf.iter_mut().zip(s.as_bytes().iter().take(7)) {
+        *dst = src as c_char;
+    }
+}
+
+/// Creates an engine with the networks at the given paths, where null
+/// selects the default file names. Returns null if either is missing
+/// or has the wrong size.
+#[no_mangle]
+pub extern "C" fn monty_engine_new(
+    policy_path: *const c_char,
+    value_path: *const c_char,
+) -> *mut MontyEngine {
+    let Some(policy) = map_network::<PolicyNetwork>(policy_path, PolicyFileDefaultName) else {
+        return ptr::null_mut();
+    };
+
+    let Some(value) = map_network::<ValueNetwork>(value_path, ValueFileDefaultName) else {
+        return ptr::null_mut();
+    };
+
+    MontyEngine::new(policy.data, value.data, Some((policy, value)))
+}
+
+/// Creates an engine with the networks embedded in the library, or
+/// returns null if it was built without them.
+#[no_mangle]
+pub extern "C" fn monty_engine_new_embedded() -> *mut MontyEngine {
+    #[cfg(feature = "embed")]
+    {
+        let (policy, value) = monty::networks::embedded::networks();
+        MontyEngine::new(policy, value, None)
+    }
+
+    #[cfg(not(feature = "embed"))]
+    ptr::null_mut()
+}
+
+/// Frees an engine, after stopping any search it is running.
+///
+/// # Safety
+/// `engine` must be null or have been created by this library, and
+/// not be used again.
+#[no_mangle]
+pub unsafe extern "C" fn monty_engine_free(engine: *mut MontyEngine) {
+    if !engine.is_null() {
+        drop(unsafe { Box::from_raw(engine) });
+    }
+}
+
+/// Sends every line of search output to `callback`, which is called from
+/// the search thread, or stops doing so if it is null. The callback may
+/// call this to replace or remove itself.
+///
+/// # Safety
+/// `engine` must be null or valid, and `callback` must be safe to call
+/// from another thread with `user_data` while searching.
+#[no_mangle]
+pub unsafe extern "C" fn monty_engine_set_callback(
+    engine: *mut MontyEngine,
+    callback: MontyCallback,
+    user_data: *mut c_void,
+) {
+    let Some(engine) = (unsafe { engine.as_mut() }) else {
+        return;
+    };
+
+    *engine.callback.lock().unwrap() = callback.map(|function| Callback {
+        function,
+        user_data,
+    });
+}
+
+/// Sets an option by its UCI name, where `value` may be null for buttons.
+///
+/// # Safety
+/// `engine` must be null or valid, and the strings null or null terminated.
+#[no_mangle]
+pub unsafe extern "C" fn monty_engine_set_option(
+    engine: *mut MontyEngine,
+    name: *const c_char,
+    value: *const c_char,
+) -> i32 {
+    let (Some(engine), Some(name)) = (unsafe { engine.as_mut() }, c_str(name)) else {
+        return MONTY_ERROR;
+    };
+
+    engine.engine.set_option(name, c_str(value));
+    MONTY_OK
+}
+
+/// Sets the position to `fen`, or the starting position if null, followed
+/// by space separated `moves` in UCI notation, if not null. Fails on an
+/// invalid FEN or illegal move, leaving the position unchanged.
+///
+/// # Safety
+/// `engine` must be null or valid, and the strings null or null terminated.
+#[no_mangle]
+pub unsafe extern "C" fn monty_engine_set_position(
+    engine: *mut MontyEngine,
+    fen: *const c_char,
+    moves: *const c_char,
+) -> i32 {
+    let Some(engine) = (unsafe { engine.as_mut() }) else {
+        return MONTY_ERROR;
+    };
+
+    let fen = if fen.is_null() {
+        ChessState::STARTPOS
+    } else {
+        match c_str(fen) {
+            Some(fen) if ChessState::is_valid_fen(fen) => fen,
+            _ => return MONTY_ERROR,
+        }
+    };
+
+    let moves = if moves.is_null() {
+        Vec::new()
+    } else {
+        match c_str(moves) {
+            Some(moves) => moves.split_whitespace().collect::<Vec<_>>(),
+            None => return MONTY_ERROR,
+        }
+    };
+
+    // reject illegal moves rather than playing null moves
+    let mut pos = ChessState::from_fen(fen);
+
+    for &mov in &moves {
+        if !pos.is_legal_uci_move(mov) {
+            return MONTY_ERROR;
+        }
+
+        pos.make_uci_move(mov);
+    }
+
+    engine.engine.set_position(fen, &moves);
+    MONTY_OK
+}
+
+/// Searches the current position, and fills `result` if not null. Fails
+/// if `limits` has no limit set, as the search would never finish.
+///
+/// # Safety
+/// `engine` and `limits` must be null or valid, and `result` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn monty_engine_search(
+    engine: *mut MontyEngine,
+    limits: *const MontyLimits,
+    result: *mut MontyResult,
+) -> i32 {
+    let (Some(engine), Some(limits)) = (unsafe { engine.as_mut() }, unsafe { limits.as_ref() })
+    else {
+        return MONTY_ERROR;
+    };
+
+    let nonzero = |x: i64| u64::try_from(x).ok().filter(|&x| x > 0);
+
+    let options = GoOptions {
+        times: limits.time.map(nonzero),
+        incs: limits.inc.map(nonzero),
+        movestogo: nonzero(i64::from(limits.movestogo)),
+        movetime: nonzero(limits.movetime).map(u128::from),
+        nodes: nonzero(limits.nodes).map(|nodes| nodes as usize),
+        depth: nonzero(i64::from(limits.depth)).map(|depth| depth as usize),
+    };
+
+    // a search without limits would never return
+    if options.times.iter().all(Option::is_none)
+        && options.movetime.is_none()
+        && options.nodes.is_none()
+        && options.depth.is_none()
+    {
+        return MONTY_ERROR;
+    }
+
+    let search = engine.engine.search(options);
+
+    if let Some(result) = unsafe { result.as_mut() } {
+        write_str(&mut result.best_move, &search.best_move);
+
+        (result.score_cp, result.mate) = match search.score {
+            Score::Cp(cp) => (cp, 0),
+            Score::Mate(moves) => (0, moves),
+        };
+
+        result.win = search.wdl.win;
+        result.draw = search.wdl.draw;
+        result.loss = search.wdl.loss;
+        result.nodes = search.nodes as u64;
+    }
+
+    MONTY_OK
+}
+
+/// Writes the space separated principal variation of the last search to
+/// `buf`, truncated to `len - 1` characters and null terminated, and
+/// returns its full length.
+///
+/// # Safety
+/// `engine` must be null or valid, and `buf` null or writable for `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn monty_engine_pv(
+    engine: *mut MontyEngine,
+    buf: *mut c_char,
+    len: usize,
+) -> usize {
+    let Some(engine) = (unsafe { engine.as_mut() }) else {
+        return 0;
+    };
+
+    let pv = engine
+        .engine
+        .wait()
+        .map_or_else(String::new, |result| result.pv.join(" "));
+
+    if !buf.is_null() && len > 0 {
+        let written = pv.len().min(len - 1);
+
+        unsafe {
+            ptr::copy_nonoverlapping(pv.as_ptr().cast(), buf, written);
+            *buf.add(written) = 0;
+        }
+    }
+
+    pv.len()
+}
+
+/// Evaluates the current position with the value network, from the
+/// perspective of the side to move.
+///
+/// # Safety
+/// `engine` must be null or valid, and `eval` null or writable.
+#[no_mangle]
+pub unsafe extern "C" fn monty_engine_evaluate(
+    engine: *mut MontyEngine,
+    eval: *mut MontyEval,
+) -> i32 {
+    let (Some(engine), Some(eval)) = (unsafe { engine.as_mut() }, unsafe { eval.as_mut() }) else {
+        return MONTY_ERROR;
+    };
+
+    let breakdown = engine.engine.eval();
+
+    eval.cp = breakdown.cp;
+    eval.win = breakdown.raw.win;
+    eval.draw = breakdown.raw.draw;
+    eval.loss = breakdown.raw.loss;
+
+    MONTY_OK
+}
+
+/// Writes up to `len` legal moves of the current position, with the
+/// probability the policy network gives them and from most to least
+/// likely, to `moves`, and returns the number of legal moves.
+///
+/// # Safety
+/// `engine` must be null or valid, and `moves` null or writable for `len` entries.
+#[no_mangle]
+pub unsafe extern "C" fn monty_engine_policy(
+    engine: *mut MontyEngine,
+    moves: *mut MontyMovePolicy,
+    len: usize,
+) -> usize {
+    let Some(engine) = (unsafe { engine.as_mut() }) else {
+        return 0;
+    };
+
+    let policy = engine.engine.policy();
+
+    if !moves.is_null() {
+        for (i, (mov, probability)) in policy.iter().take(len).enumerate() {
+            let entry = unsafe { &mut *moves.add(i) };
+            write_str(&mut entry.mov, mov);
+            entry.probability = *probability;
+        }
+    }
+
+    policy.len()
+}
@@ -0,0 +1,98 @@
+/*
+ * Smoke test for the C API, built and ran from the repository root with
+ *
+ *   make capi-harness
+ *   LD_LIBRARY_PATH=target/release target/release/capi-harness [policy path] [value path]
+ *
+ * and exits with a nonzero status if any check fails.
+ */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "monty.h"
+
+static int failures = 0;
+
+#define CHECK(cond)                                                   \
+    do {                                                              \
+        if (!(cond)) {                                                \
+            fprintf(stderr, "%s:%d: check failed: %s\n", __FILE__,   \
+                    __LINE__, #cond);                                 \
+            failures++;                                               \
+        }                                                             \
+    } while (0)
+
+static void on_line(const char *line, void *user_data) {
+    int *lines = user_data;
+    (*lines)++;
+    printf("  %s\n", line);
+}
+
+int main(int argc, char **argv) {
+    const char *policy = argc > 1 ? argv[1] : NULL;
+    const char *value = argc > 2 ? argv[2] : NULL;
+
+    CHECK(monty_engine_new("does-not-exist.network", "does-not-exist.network") == NULL);
+
+    MontyEngine *engine = monty_engine_new(policy, value);
+    if (engine == NULL) {
+        fprintf(stderr, "could not load networks\n");
+        return 1;
+    }
+
+    int lines = 0;
+    monty_engine_set_callback(engine, on_line, &lines);
+
+    CHECK(monty_engine_set_option(engine, "Threads", "1") == MONTY_OK);
+    CHECK(monty_engine_set_position(engine, NULL, "e2e4 e7e5 g1f3") == MONTY_OK);
+
+    CHECK(monty_engine_set_position(engine, "not a fen", NULL) == MONTY_ERROR);
+    CHECK(monty_engine_set_position(engine, NULL, "e2e5") == MONTY_ERROR);
+
+    MontyEval eval;
+    CHECK(monty_engine_evaluate(engine, &eval) == MONTY_OK);
+    printf("eval: cp %d wdl %.3f %.3f %.3f\n", eval.cp, eval.win, eval.draw, eval.loss);
+    CHECK(eval.win + eval.draw + eval.loss > 0.99f && eval.win + eval.draw + eval.loss < 1.01f);
+
+    MontyMovePolicy moves[5];
+    size_t count = monty_engine_policy(engine, moves, 5);
+    CHECK(count == 29);
+    for (size_t i = 0; i < 5 && i < count; i++) {
+        printf("policy: %s %.3f\n", moves[i].mov, moves[i].probability);
+    }
+    CHECK(moves[0].probability >= moves[4].probability);
+
+    MontyLimits limits;
+    memset(&limits, 0, sizeof limits);
+
+    MontyResult result;
+    CHECK(monty_engine_search(engine, &limits, &result) == MONTY_ERROR);
+
+    limits.nodes = 1000;
+    CHECK(monty_engine_search(engine, &limits, &result) == MONTY_OK);
+    printf("bestmove %s score cp %d mate %d wdl %.3f %.3f %.3f nodes %llu\n", result.best_move,
+           result.score_cp, result.mate, result.win, result.draw, result.loss,
+           (unsigned long long)result.nodes);
+    CHECK(strlen(result.best_move) >= 4);
+    CHECK(lines > 0);
+
+    char pv[256];
+    size_t pv_len = monty_engine_pv(engine, pv, sizeof pv);
+    printf("pv %s\n", pv);
+    CHECK(pv_len > 0 && strncmp(pv, result.best_move, strlen(result.best_move)) == 0);
+
+    char small[3];
+    CHECK(monty_engine_pv(engine, small, sizeof small) == pv_len);
+    CHECK(strlen(small) == 2);
+
+    monty_engine_free(engine);
+
+    if (failures > 0) {
+        fprintf(stderr, "%d checks failed\n", failures);
+        return 1;
+    }
+
+    printf("all checks passed\n");
+    return 0;
+}
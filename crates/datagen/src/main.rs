@@ -9,7 +9,9 @@ use monty::{
     chess::ChessState,
     mcts::MctsParams,
     networks::{self, PolicyNetwork, ValueNetwork},
-    read_into_struct_unchecked, uci, MappedWeights,
+    read_into_struct_unchecked,
+    tablebase::Tablebases,
+    uci, MappedWeights,
 };
 
 use std::{
@@ -134,8 +136,7 @@ impl Destination {
     }
 
     pub fn report(&self) {
-        if self.searches != 0 {
-            let average_iters = self.iters / self.searches;
+        if let Some(average_iters) = self.iters.checked_div(self.searches) {
             println!("average iters {average_iters}");
         }
         println!(
@@ -178,14 +179,28 @@ pub fn run_datagen(
         buf.trim().split('\n').collect::<Vec<&str>>()
     });
 
+    let tablebases = opts
+        .tablebases
+        .as_deref()
+        .map(|dir| Tablebases::load(dir).expect("failed to load tablebases"));
+    let tablebases = tablebases.as_ref();
+
     std::thread::scope(|s| {
         for _ in 0..opts.threads {
             let params = params.clone();
             std::thread::sleep(Duration::from_millis(10));
             let this_book = book.clone();
             let this_dest = dest_mutex.clone();
+            let gumbel_nodes = opts.gumbel.then_some(opts.nodes);
             s.spawn(move || {
-                let mut thread = DatagenThread::new(params.clone(), stop, this_book, this_dest);
+                let mut thread = DatagenThread::new(
+                    params.clone(),
+                    stop,
+                    this_book,
+                    this_dest,
+                    gumbel_nodes,
+                    tablebases,
+                );
                 thread.run(opts.policy_data, policy, value);
             });
         }
@@ -204,6 +219,8 @@ pub struct RunOptions {
     policy_data: bool,
     nodes: usize,
     out_path: String,
+    gumbel: bool,
+    tablebases: Option<String>,
 }
 
 pub fn parse_args(args: Args) -> Option<RunOptions> {
@@ -219,11 +236,13 @@ pub fn parse_args(args: Args) -> Option<RunOptions> {
         match arg.as_str() {
             "bench" => return None,
             "--policy-data" => opts.policy_data = true,
+            "--gumbel" => opts.gumbel = true,
             "-t" | "--threads" => mode = 1,
             "-b" | "--book" => mode = 2,
             "-n" | "--nodes" => mode = 3,
             "-o" | "--output" => mode = 4,
             "-g" | "--games" => mode = 5,
+            "--tablebases" => mode = 6,
             _ => match mode {
                 1 => {
                     opts.threads = arg.parse().expect("can't parse");
@@ -245,10 +264,19 @@ pub fn parse_args(args: Args) -> Option<RunOptions> {
                     opts.games = arg.parse().expect("can't parse");
                     mode = 0;
                 }
+                6 => {
+                    opts.tablebases = Some(arg);
+                    mode = 0;
+                }
                 _ => println!("unrecognised argument {arg}"),
             },
         }
     }
 
+    if opts.gumbel && opts.nodes == 0 {
+        println!("--gumbel requires a node budget, set with --nodes");
+        opts.gumbel = false;
+    }
+
     Some(opts)
 }
@@ -2,8 +2,9 @@ use crate::{Destination, Rand};
 
 use monty::{
     chess::{ChessState, GameState},
-    mcts::{Limits, MctsParams, Searcher},
+    mcts::{Limits, MctsParams, Searcher, SequentialHalving},
     networks::{PolicyNetwork, ValueNetwork},
+    tablebase::Tablebases,
     tree::Tree,
 };
 use montyformat::{MontyFormat, MontyValueFormat, SearchData};
@@ -19,6 +20,8 @@ pub struct DatagenThread<'a> {
     dest: Arc<Mutex<Destination>>,
     stop: &'a AtomicBool,
     book: Option<Vec<&'a str>>,
+    gumbel_nodes: Option<usize>,
+    tablebases: Option<&'a Tablebases>,
 }
 
 impl<'a> DatagenThread<'a> {
@@ -27,6 +30,8 @@ impl<'a> DatagenThread<'a> {
         stop: &'a AtomicBool,
         book: Option<Vec<&'a str>>,
         dest: Arc<Mutex<Destination>>,
+        gumbel_nodes: Option<usize>,
+        tablebases: Option<&'a Tablebases>,
     ) -> Self {
         Self {
             rng: Rand::with_seed(),
@@ -34,6 +39,8 @@ impl<'a> DatagenThread<'a> {
             dest,
             stop,
             book,
+            gumbel_nodes,
+            tablebases,
         }
     }
 
@@ -62,14 +69,26 @@ impl<'a> DatagenThread<'a> {
             return;
         }
 
+        // sequential halving searches a fixed budget rather than
+        // stopping on kld gain, and replaces dirichlet noise
         let limits = Limits {
             max_depth: 64,
-            max_nodes: 100000,
+            max_nodes: self.gumbel_nodes.unwrap_or(100000),
             max_time: None,
             opt_time: None,
-            kld_min_gain: Some(0.000005),
+            gumbel: self.gumbel_nodes.is_some(),
+            deterministic: false,
+            root_noise: None,
+            move_temperature: None,
+            kld_min_gain: if self.gumbel_nodes.is_some() {
+                None
+            } else {
+                Some(0.000005)
+            },
         };
 
+        let use_dirichlet_noise = !limits.gumbel;
+
         let mut result = 0.5;
 
         let mut tree = Tree::new_mb(8, 1);
@@ -98,9 +117,11 @@ impl<'a> DatagenThread<'a> {
 
             let abort = AtomicBool::new(false);
             tree.set_root_position(&position);
-            let searcher = Searcher::new(&tree, &self.params, policy, value, &abort);
+            let searcher =
+                Searcher::new(&tree, &self.params, policy, value, &abort, self.tablebases);
 
-            let (best_move, score, iters) = searcher.search(1, limits, false, &mut 0, true, temp);
+            let (best_move, score, iters) =
+                searcher.search(1, limits, false, &mut 0, use_dirichlet_noise, temp);
 
             searches += 1;
             total_iters += iters;
@@ -122,10 +143,19 @@ impl<'a> DatagenThread<'a> {
 
                 let actions = tree[tree.root_node()].actions();
 
+                let improved = limits
+                    .gumbel
+                    .then(|| SequentialHalving::improved_policy(&tree, &self.params));
+
                 for action in 0..tree[tree.root_node()].num_actions() {
                     let node = &tree[actions + action];
                     let mov = node.parent_move();
-                    let visits = node.visits().min(u32::MAX as u64) as u32;
+                    let visits = if let Some(policy) = &improved {
+                        // completed-Q policy target, stored as pseudo-visits
+                        (policy[action] * 1_000_000.0) as u32
+                    } else {
+                        node.visits().min(u32::MAX as u64) as u32
+                    };
                     dist.push((mov, visits));
                 }
 
@@ -140,7 +170,15 @@ impl<'a> DatagenThread<'a> {
 
             position.make_move(best_move);
 
-            let game_state = position.game_state();
+            // adjudicate as soon as the tablebases know the result
+            let game_state = match position.game_state() {
+                GameState::Ongoing => self
+                    .tablebases
+                    .and_then(|tbs| tbs.probe(&position.board()))
+                    .unwrap_or(GameState::Ongoing),
+                state => state,
+            };
+
             match game_state {
                 GameState::Ongoing => {}
                 GameState::Draw => break,
@@ -0,0 +1,159 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Write},
+};
+
+use montyformat::{FastDeserialise, MontyFormat, MontyValueFormat, PgnEval, PgnGame, PgnMove};
+
+const USAGE: &str = "usage: binpack_to_pgn <policy|value> <input> <output|-> \
+                     [--from <game>] [--to <game>] [--count <games>] [--top <moves>]";
+
+/// Writes the games of a policy or value binpack as PGN, with scores as
+/// `[%eval]` comments in the scale of `MontyValueFormat`, and for policy
+/// data the most visited root moves. Games are numbered from zero, and
+/// `--from` and `--to` select a half-open range of them.
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let (Some(format), Some(inp_path), Some(out_path)) = (args.first(), args.get(1), args.get(2))
+    else {
+        eprintln!("{USAGE}");
+        return;
+    };
+
+    let policy = match format.as_str() {
+        "policy" => true,
+        "value" => false,
+        _ => {
+            eprintln!("{USAGE}");
+            return;
+        }
+    };
+
+    let option = |name: &str| {
+        args.iter().position(|arg| arg == name).map(|i| {
+            args.get(i + 1)
+                .and_then(|val| val.parse().ok())
+                .expect(USAGE)
+        })
+    };
+
+    let from = option("--from").unwrap_or(0);
+    let to = option("--to").unwrap_or(usize::MAX);
+    let count = option("--count").unwrap_or(usize::MAX);
+    let top = option("--top").unwrap_or(5);
+
+    let mut reader = BufReader::new(File::open(inp_path).unwrap());
+
+    let mut writer: Box<dyn Write> = if out_path == "-" {
+        Box::new(BufWriter::new(std::io::stdout()))
+    } else {
+        Box::new(BufWriter::new(File::create(out_path).unwrap()))
+    };
+
+    let event = std::path::Path::new(inp_path)
+        .file_name()
+        .map_or(inp_path.clone(), |name| name.to_string_lossy().into_owned());
+
+    let mut buffer = Vec::new();
+    let mut written = 0;
+
+    for index in 0..to.min(from.saturating_add(count)) {
+        let mut game = if index < from {
+            let skipped = if policy {
+                MontyFormat::deserialise_fast_into_buffer(&mut reader, &mut buffer)
+            } else {
+                MontyValueFormat::deserialise_fast_into_buffer(&mut reader, &mut buffer)
+            };
+
+            match skipped {
+                Ok(()) => continue,
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => panic!("{err}"),
+            }
+        } else {
+            let game = if policy {
+                MontyFormat::deserialise_from(&mut reader).map(|game| policy_game(game, top))
+            } else {
+                MontyValueFormat::deserialise_from(&mut reader, Vec::new()).map(value_game)
+            };
+
+            match game {
+                Ok(game) => game,
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => panic!("{err}"),
+            }
+        };
+
+        game.set_tag("Event", &event);
+        game.set_tag("Site", "?");
+        game.set_tag("Date", "????.??.??");
+        game.set_tag("Round", &index.to_string());
+        game.set_tag("White", "?");
+        game.set_tag("Black", "?");
+
+        game.serialise_into(&mut writer).unwrap();
+        written += 1;
+    }
+
+    writer.flush().unwrap();
+
+    eprintln!("Games written : {written}");
+}
+
+/// Converts a win probability for the side to move into the centipawn
+/// scale of `MontyValueFormat::push`, from white's point of view.
+fn to_centipawns(stm: usize, mut score: f32) -> i32 {
+    if stm == 1 {
+        score = 1.0 - score;
+    }
+
+    i32::from(-(400.0 * (1.0 / score - 1.0).ln()) as i16)
+}
+
+fn policy_game(data: MontyFormat, top: usize) -> PgnGame {
+    let mut game = PgnGame::new(data.startpos, data.castling);
+    game.result = Some(data.result);
+
+    let mut pos = data.startpos;
+
+    for search in data.moves {
+        let mut mov = PgnMove::new(search.best_move);
+        mov.eval = Some(PgnEval::Centipawns(to_centipawns(pos.stm(), search.score)));
+
+        let mut comment = format!("score {:.3}", search.score);
+
+        if let Some(mut dist) = search.visit_distribution {
+            let total = dist.iter().map(|&(_, visits)| visits).sum::<u32>().max(1);
+
+            dist.sort_by_key(|&(_, visits)| std::cmp::Reverse(visits));
+
+            for &(mov, visits) in dist.iter().take(top) {
+                let share = 100.0 * visits as f32 / total as f32;
+                comment.push_str(&format!(
+                    " {} {share:.1}%",
+                    mov.to_san(&pos, &data.castling)
+                ));
+            }
+        }
+
+        mov.comment = Some(comment);
+        pos.make(mov.mov, &data.castling);
+        game.push(mov);
+    }
+
+    game
+}
+
+fn value_game(data: MontyValueFormat) -> PgnGame {
+    let mut game = PgnGame::new(data.startpos, data.castling);
+    game.result = Some(data.result);
+
+    for search in data.moves {
+        let mut mov = PgnMove::new(search.best_move);
+        mov.eval = Some(PgnEval::Centipawns(i32::from(search.score)));
+        game.push(mov);
+    }
+
+    game
+}
@@ -0,0 +1,174 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+};
+
+use montyformat::{MontyValueFormat, PgnEval, PgnGame, PgnReader, SearchResult};
+
+const USAGE: &str = "usage: pgn_to_binpack <input> <output> [--min-elo <elo>] \
+                     [--min-time <seconds>] [--max-time <seconds>] [--min-evals <fraction>]";
+
+/// Converts PGN games with `[%eval]` comments into a value binpack. As with
+/// engine searches, the eval after a move is used as the score of the
+/// position it was played from. Games are cut at the first move without an
+/// eval, and skipped if that leaves fewer than `--min-evals` of their moves
+/// (0.9 by default), if unfinished, or if outside the rating and time
+/// control filters, where time controls are estimated as base + 40 * inc.
+fn main() {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let (Some(inp_path), Some(out_path)) = (args.first(), args.get(1)) else {
+        eprintln!("{USAGE}");
+        return;
+    };
+
+    let option = |name: &str| {
+        args.iter().position(|arg| arg == name).map(|i| {
+            args.get(i + 1)
+                .and_then(|val| val.parse::<f64>().ok())
+                .expect(USAGE)
+        })
+    };
+
+    let min_elo = option("--min-elo");
+    let min_time = option("--min-time");
+    let max_time = option("--max-time");
+    let min_evals = option("--min-evals").unwrap_or(0.9);
+
+    let reader = PgnReader::new(BufReader::new(File::open(inp_path).unwrap()));
+    let mut writer = BufWriter::new(File::create(out_path).unwrap());
+
+    let mut stats = Stats::default();
+
+    for game in reader {
+        stats.read += 1;
+
+        let game = match game {
+            Ok(game) => game,
+            Err(err) => {
+                stats.invalid += 1;
+                eprintln!("Skipping: {err}");
+                continue;
+            }
+        };
+
+        let Some(result) = game.result else {
+            stats.unfinished += 1;
+            continue;
+        };
+
+        if !is_supported_variant(&game) {
+            stats.variant += 1;
+            continue;
+        }
+
+        let rating = |tag| game.tag(tag).and_then(|elo| elo.parse::<f64>().ok());
+
+        if min_elo.is_some_and(|min| {
+            [rating("WhiteElo"), rating("BlackElo")]
+                .iter()
+                .any(|elo| elo.is_none_or(|elo| elo < min))
+        }) {
+            stats.rating += 1;
+            continue;
+        }
+
+        let time = game.tag("TimeControl").and_then(estimated_time);
+
+        if min_time.is_some_and(|min| time.is_none_or(|time| time < min))
+            || max_time.is_some_and(|max| time.is_none_or(|time| time > max))
+        {
+            stats.time_control += 1;
+            continue;
+        }
+
+        let moves = game
+            .moves
+            .iter()
+            .map_while(|mov| {
+                Some(SearchResult {
+                    best_move: mov.mov,
+                    score: to_score(mov.eval?)?,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if moves.is_empty() || (moves.len() as f64) < min_evals * game.moves.len() as f64 {
+            stats.evals += 1;
+            continue;
+        }
+
+        stats.positions += moves.len();
+        stats.written += 1;
+
+        let value = MontyValueFormat {
+            startpos: game.startpos,
+            castling: game.castling,
+            result,
+            moves,
+        };
+
+        value.serialise_into(&mut writer).unwrap();
+
+        if stats.written % 16384 == 0 {
+            println!("Converted {} games.", stats.written);
+        }
+    }
+
+    println!("Games read      : {}", stats.read);
+    println!("Games written   : {}", stats.written);
+    println!("Positions       : {}", stats.positions);
+    println!("Invalid         : {}", stats.invalid);
+    println!("Unfinished      : {}", stats.unfinished);
+    println!("Other variant   : {}", stats.variant);
+    println!("Rating filter   : {}", stats.rating);
+    println!("Time filter     : {}", stats.time_control);
+    println!("Too few evals   : {}", stats.evals);
+}
+
+#[derive(Default)]
+struct Stats {
+    read: usize,
+    written: usize,
+    positions: usize,
+    invalid: usize,
+    unfinished: usize,
+    variant: usize,
+    rating: usize,
+    time_control: usize,
+    evals: usize,
+}
+
+/// Converts an eval into the scale of `MontyValueFormat::push`, where a
+/// score of `cp` is a win probability of `1 / (1 + exp(-cp / 400))`, with
+/// mates as certain results.
+fn to_score(eval: PgnEval) -> Option<i16> {
+    match eval {
+        PgnEval::Centipawns(cp) => Some(cp.clamp(-i32::from(i16::MAX), i32::from(i16::MAX)) as i16),
+        PgnEval::Mate(0) => None,
+        PgnEval::Mate(moves) => Some(i16::MAX * moves.signum() as i16),
+    }
+}
+
+fn is_supported_variant(game: &PgnGame) -> bool {
+    let Some(variant) = game.tag("Variant") else {
+        return true;
+    };
+
+    let variant = variant.to_lowercase();
+
+    ["standard", "chess", "normal", "from position"].contains(&variant.as_str())
+        || variant.contains("960")
+        || variant.contains("fischer")
+}
+
+/// Estimates the length of a game in seconds from a `TimeControl` tag such
+/// as `300+3`, or `40/7200:3600` using its first period.
+fn estimated_time(time_control: &str) -> Option<f64> {
+    let period = time_control.split(':').next()?;
+    let period = period.split_once('/').map_or(period, |(_, period)| period);
+
+    let (base, inc) = period.split_once('+').unwrap_or((period, "0"));
+
+    Some(base.parse::<f64>().ok()? + 40.0 * inc.parse::<f64>().ok()?)
+}
@@ -0,0 +1,176 @@
+use montyformat::{
+    chess::{Castling, Flag, Position},
+    PgnEval, PgnGame, PgnMove, PgnReader,
+};
+
+const PGN: &str = r#"[Event "Test"]
+[Site "?"]
+[Date "2024.01.01"]
+[Round "1"]
+[White "A \"quoted\" name"]
+[Black "B"]
+[Result "1-0"]
+
+{Opening comment} 1. e4 {[%eval 0.25] [%clk 0:03:00] best by test} e5 2. Nf3!
+Nc6 (2... d6 3. d4 (3. Bc4 Be7) 3... exd4) 3. Bb5 $1 a6?! ; a line comment
+% an escaped line
+4. Ba4 Nf6 5. O-O {[%eval #4]} Be7 6. Re1 1-0
+
+[Event "960"]
+[Variant "Chess960"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/8/1R2K1R1 w KQ - 0 1"]
+[Result "1/2-1/2"]
+
+1. O-O Kd7 2. Rb7+ Kc6 1/2-1/2
+
+1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0
+
+[Event "Broken"]
+
+1. e4 e5 2. Ke3 1-0
+
+[Event "Unfinished"]
+[Result "*"]
+
+1. d4 d5 2. c4
+
+[Event "Setup"]
+[SetUp "1"]
+[FEN "8/P7/8/8/8/8/k7/4K3 b - - 0 60"]
+[Result "0-1"]
+
+60... Kb3 61. a8=Q Kc3 0-1
+"#;
+
+fn main() {
+    let games = PgnReader::new(PGN.as_bytes())
+        .keep_variations(true)
+        .collect::<Vec<_>>();
+
+    assert_eq!(games.len(), 6);
+
+    let err = games[3].as_ref().err().unwrap();
+    assert!(
+        err.to_string().contains("game 4: illegal move 2.Ke3"),
+        "{err}"
+    );
+
+    let game = games[0].as_ref().unwrap();
+    assert_eq!(game.tag("White"), Some("A \"quoted\" name"));
+    assert_eq!(game.result, Some(1.0));
+    assert_eq!(game.comment.as_deref(), Some("Opening comment"));
+    assert_eq!(game.moves.len(), 11);
+    assert_eq!(game.moves[0].eval, Some(PgnEval::Centipawns(25)));
+    assert_eq!(
+        game.moves[0].comment.as_deref(),
+        Some("[%clk 0:03:00] best by test")
+    );
+    assert_eq!(game.moves[2].nags, [1]);
+    assert_eq!(game.moves[4].nags, [1]);
+    assert_eq!(game.moves[5].nags, [6]);
+    assert_eq!(game.moves[5].comment.as_deref(), Some("a line comment"));
+    assert_eq!(game.moves[8].eval, Some(PgnEval::Mate(4)));
+    assert_eq!(game.moves[3].variations.len(), 1);
+    assert_eq!(game.moves[3].variations[0].len(), 3);
+    assert_eq!(game.moves[3].variations[0][1].variations[0].len(), 2);
+
+    let skipped = PgnReader::new(PGN.as_bytes()).next().unwrap().unwrap();
+    assert_eq!(skipped.moves.len(), 11);
+    assert!(skipped.moves[3].variations.is_empty());
+
+    let game = games[1].as_ref().unwrap();
+    assert!(game.castling.is_chess960());
+    assert_eq!(game.moves[0].mov.flag(), Flag::KS);
+    assert_eq!(game.result, Some(0.5));
+    assert!(game
+        .to_string()
+        .contains("[FEN \"4k3/8/8/8/8/8/8/1R2K1R1 w GB - 0 1\"]"));
+
+    let game = games[2].as_ref().unwrap();
+    assert!(game.tags.is_empty());
+    assert_eq!(game.moves.len(), 7);
+    assert_eq!(game.result, Some(1.0));
+    assert!(game.to_string().contains("4. Qxf7# 1-0"));
+
+    let game = games[4].as_ref().unwrap();
+    assert_eq!(game.moves.len(), 3);
+    assert_eq!(game.result, None);
+
+    let game = games[5].as_ref().unwrap();
+    assert_eq!(game.startpos.stm(), 1);
+    assert_eq!(game.moves.len(), 3);
+    assert_eq!(game.result, Some(0.0));
+    assert!(game.to_string().contains("60... Kb3 61. a8=Q Kc3 0-1"));
+
+    for game in games.iter().flatten() {
+        let text = game.to_string();
+        let again = PgnReader::new(text.as_bytes())
+            .keep_variations(true)
+            .next()
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(summary(game), summary(&again), "{text}");
+        assert_eq!(text, again.to_string());
+    }
+
+    let mut castling = Castling::default();
+    let pos = Position::parse_fen(montyformat::chess::STARTPOS, &mut castling);
+    let mut game = PgnGame::new(pos, castling);
+    game.set_tag("Event", "Written");
+
+    let mut after = pos;
+    for (san, cp) in [("d4", 20), ("Nf6", -15), ("c4", 31)] {
+        let mut mov = PgnMove::new(after.parse_san(san, &castling).unwrap());
+        mov.eval = Some(PgnEval::Centipawns(cp));
+        after.make(mov.mov, &castling);
+        game.push(mov);
+    }
+
+    game.result = Some(0.5);
+
+    let mut written = Vec::new();
+    game.serialise_into(&mut written).unwrap();
+    let written = String::from_utf8(written).unwrap();
+
+    assert_eq!(
+        written,
+        "[Event \"Written\"]\n[Result \"1/2-1/2\"]\n\n\
+         1. d4 {[%eval 0.20]} 1... Nf6 {[%eval -0.15]} 2. c4 {[%eval 0.31]} 1/2-1/2\n\n"
+    );
+
+    println!("all tests passed");
+}
+
+fn summary(game: &PgnGame) -> String {
+    fn line(moves: &[PgnMove], castling: &Castling) -> String {
+        moves
+            .iter()
+            .map(|mov| {
+                let variations = mov
+                    .variations
+                    .iter()
+                    .map(|variation| format!("({})", line(variation, castling)))
+                    .collect::<String>();
+
+                format!(
+                    "{} {:?} {:?} {:?} {variations}",
+                    mov.mov.to_uci(castling),
+                    mov.nags,
+                    mov.eval,
+                    mov.comment
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    format!(
+        "{} {:?} {:?} {}",
+        game.startpos.as_fen(),
+        game.result,
+        game.comment,
+        line(&game.moves, &game.castling)
+    )
+}
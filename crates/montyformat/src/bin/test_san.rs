@@ -0,0 +1,214 @@
+use montyformat::chess::{Castling, Position};
+
+const POSITIONS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+    "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+];
+
+const KNOWN: [(&str, &str, &str); 12] = [
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "g1f3",
+        "Nf3",
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "e1g1",
+        "O-O",
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "e1c1",
+        "O-O-O",
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "d5e6",
+        "dxe6",
+    ),
+    ("7k/8/8/8/8/8/8/R5RK w - - 0 1", "a1b1", "Rab1"),
+    ("R7/8/7k/8/8/8/8/R6K w - - 0 1", "a1a4", "R1a4"),
+    ("6k1/8/8/8/8/Q7/8/Q1Q4K w - - 0 1", "a1b2", "Qa1b2"),
+    ("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", "a1a8", "Ra8#"),
+    ("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q", "b8=Q+"),
+    (
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "e5f6",
+        "exf6",
+    ),
+    ("4k3/8/8/8/8/8/8/1R2K1R1 w GB - 0 1", "e1b1", "O-O-O"),
+    (
+        "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+        "c5d7",
+        "Nxd7",
+    ),
+];
+
+const LENIENT: [(&str, &str, &str); 9] = [
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "Ng1-f3",
+        "g1f3",
+    ),
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "e2-e4",
+        "e2e4",
+    ),
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "Pe4!?",
+        "e2e4",
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "0-0-0",
+        "e1c1",
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "de6",
+        "d5e6",
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        "Qf3xf6",
+        "f3f6",
+    ),
+    ("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b8n", "b7b8n"),
+    (
+        "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        "exf6 e.p.",
+        "e5f6",
+    ),
+    ("4k3/8/8/8/8/8/8/1R2K1R1 w GB - 0 1", "0-0", "e1g1"),
+];
+
+const INVALID: [(&str, &str); 6] = [
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "e5",
+    ),
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "O-O",
+    ),
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "Zf3",
+    ),
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "",
+    ),
+    ("7k/8/8/8/8/8/8/R5RK w - - 0 1", "Rb1"),
+    ("4k3/1P6/8/8/8/8/8/4K3 w - - 0 1", "b8=K"),
+];
+
+fn main() {
+    for (fen, uci, san) in KNOWN {
+        let mut castling = Castling::default();
+        let pos = Position::parse_fen(fen, &mut castling);
+        let mov = find_uci(&pos, &castling, uci);
+
+        assert_eq!(mov.to_san(&pos, &castling), san, "{fen} {uci}");
+    }
+
+    for (fen, san, uci) in LENIENT {
+        let mut castling = Castling::default();
+        let pos = Position::parse_fen(fen, &mut castling);
+        let mov = find_uci(&pos, &castling, uci);
+
+        assert_eq!(pos.parse_san(san, &castling), Some(mov), "{fen} {san}");
+    }
+
+    for (fen, san) in INVALID {
+        let mut castling = Castling::default();
+        let pos = Position::parse_fen(fen, &mut castling);
+
+        assert_eq!(pos.parse_san(san, &castling), None, "{fen} {san}");
+    }
+
+    for fen in POSITIONS {
+        let mut castling = Castling::default();
+        let pos = Position::parse_fen(fen, &mut castling);
+        let count = round_trip(&pos, &castling, 3);
+        println!("{fen}: {count} moves");
+    }
+
+    println!("all tests passed");
+}
+
+fn find_uci(pos: &Position, castling: &Castling, uci: &str) -> montyformat::chess::Move {
+    let mut found = None;
+    pos.map_legal_moves(castling, |mov| {
+        if mov.to_uci(castling) == uci {
+            found = Some(mov);
+        }
+    });
+
+    found.unwrap_or_else(|| panic!("{uci} is not legal in {}", pos.as_fen()))
+}
+
+/// Checks that every legal move in the tree of `depth` plies has a distinct
+/// SAN that parses back to it, also with its marks left out.
+fn round_trip(pos: &Position, castling: &Castling, depth: u8) -> u64 {
+    let mut moves = Vec::new();
+    pos.map_legal_moves(castling, |mov| moves.push(mov));
+
+    let sans = moves
+        .iter()
+        .map(|mov| mov.to_san(pos, castling))
+        .collect::<Vec<_>>();
+
+    let mut count = 0;
+
+    for (&mov, san) in moves.iter().zip(&sans) {
+        let fen = pos.as_fen();
+
+        assert_eq!(
+            sans.iter().filter(|&other| other == san).count(),
+            1,
+            "{fen} {san}"
+        );
+        assert_eq!(pos.parse_san(san, castling), Some(mov), "{fen} {san}");
+
+        let bare = san.replace(['x', '=', '+', '#'], "");
+        assert_eq!(pos.parse_san(&bare, castling), Some(mov), "{fen} {bare}");
+
+        let uci = mov.to_uci(castling);
+        assert_eq!(pos.parse_san(&uci, castling), Some(mov), "{fen} {uci}");
+
+        let mut new = *pos;
+        new.make(mov, castling);
+
+        let mut replies = 0;
+        new.map_legal_moves(castling, |_| replies += 1);
+
+        let suffix = match (new.in_check(), replies) {
+            (true, 0) => Some('#'),
+            (true, _) => Some('+'),
+            (false, _) => None,
+        };
+
+        assert_eq!(
+            san.chars().last().filter(|ch| "+#".contains(*ch)),
+            suffix,
+            "{fen} {san}"
+        );
+
+        count += 1;
+
+        if depth > 1 {
+            count += round_trip(&new, castling, depth - 1);
+        }
+    }
+
+    count
+}
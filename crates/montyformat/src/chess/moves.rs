@@ -1,6 +1,10 @@
 use crate::bitloop;
 
-use super::{consts::Flag, frc::Castling};
+use super::{
+    consts::{Flag, Piece},
+    frc::Castling,
+    position::Position,
+};
 
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
 pub struct Move {
@@ -79,6 +83,78 @@ impl Move {
 
         format!("{}{}{}", idx_to_sq(self.src()), idx_to_sq(to), promo)
     }
+
+    /// Writes a legal move of `pos` in standard algebraic notation, with
+    /// the file, rank or square it is from when another piece of the same
+    /// kind can reach the same square, and a `+` or `#` if it gives check.
+    pub fn to_san(self, pos: &Position, castling: &Castling) -> String {
+        let idx_to_sq = |i| format!("{}{}", ((i & 7) as u8 + b'a') as char, (i / 8) + 1);
+
+        let mut san = match self.flag() {
+            Flag::KS => "O-O".to_string(),
+            Flag::QS => "O-O-O".to_string(),
+            _ => {
+                let piece = pos.get_pc(1 << self.src());
+                let mut san = String::new();
+
+                if piece == Piece::PAWN {
+                    if self.is_capture() {
+                        san.push((b'a' + (self.src() & 7) as u8) as char);
+                    }
+                } else {
+                    san.push(b"..PNBRQK"[piece] as char);
+
+                    let mut others = Vec::new();
+                    pos.map_legal_moves(castling, |mov| {
+                        if mov != self
+                            && mov.to() == self.to()
+                            && ![Flag::KS, Flag::QS].contains(&mov.flag())
+                            && pos.get_pc(1 << mov.src()) == piece
+                        {
+                            others.push(mov.src());
+                        }
+                    });
+
+                    let file = self.src() & 7;
+                    let rank = self.src() / 8;
+
+                    if !others.is_empty() {
+                        if others.iter().all(|sq| sq & 7 != file) {
+                            san.push((b'a' + file as u8) as char);
+                        } else if others.iter().all(|sq| sq / 8 != rank) {
+                            san.push((b'1' + rank as u8) as char);
+                        } else {
+                            san.push_str(&idx_to_sq(self.src()));
+                        }
+                    }
+                }
+
+                if self.is_capture() {
+                    san.push('x');
+                }
+
+                san.push_str(&idx_to_sq(self.to()));
+
+                if self.is_promo() {
+                    san.push('=');
+                    san.push(b"...NBRQ"[self.promo_pc()] as char);
+                }
+
+                san
+            }
+        };
+
+        let mut after = *pos;
+        after.make(self, castling);
+
+        if after.in_check() {
+            let mut replies = 0;
+            after.map_legal_moves(castling, |_| replies += 1);
+            san.push(if replies == 0 { '#' } else { '+' });
+        }
+
+        san
+    }
 }
 
 #[inline]
@@ -640,6 +640,108 @@ impl Position {
         });
     }
 
+    /// Finds the legal move written as `san` in standard algebraic notation.
+    /// Capture, check and annotation marks are optional, and so are `=` for
+    /// promotions and the case of the promotion piece. Castling may use
+    /// zeros, moves may give both squares as in `Ng1-f3`, and moves in UCI
+    /// notation are accepted. Returns `None` if no move or more than one
+    /// move matches.
+    pub fn parse_san(&self, san: &str, castling: &Castling) -> Option<Move> {
+        let san = san
+            .trim_end_matches(['+', '#', '!', '?'])
+            .trim_end_matches("e.p.")
+            .trim();
+
+        let castle = match san {
+            "O-O" | "0-0" => Some(Flag::KS),
+            "O-O-O" | "0-0-0" => Some(Flag::QS),
+            _ => None,
+        };
+
+        let mut found = None;
+        let mut matches = 0;
+
+        if let Some(flag) = castle {
+            self.map_legal_moves(castling, |mov| {
+                if mov.flag() == flag {
+                    found = Some(mov);
+                }
+            });
+
+            return found;
+        }
+
+        self.map_legal_moves(castling, |mov| {
+            if mov.to_uci(castling) == san {
+                found = Some(mov);
+            }
+        });
+
+        if found.is_some() {
+            return found;
+        }
+
+        let chars = san
+            .chars()
+            .filter(|ch| !matches!(ch, 'x' | ':' | '-' | '='))
+            .collect::<Vec<_>>();
+
+        let piece_of = |ch| match ch {
+            'N' | 'n' => Some(Piece::KNIGHT),
+            'B' | 'b' => Some(Piece::BISHOP),
+            'R' | 'r' => Some(Piece::ROOK),
+            'Q' | 'q' => Some(Piece::QUEEN),
+            'K' => Some(Piece::KING),
+            'P' => Some(Piece::PAWN),
+            _ => None,
+        };
+
+        // a lowercase first letter is the file of a pawn
+        let (piece, rest) = match chars.first() {
+            Some(&ch) if ch.is_ascii_uppercase() => (piece_of(ch)?, &chars[1..]),
+            _ => (Piece::PAWN, &chars[..]),
+        };
+
+        let (promo, rest) = match rest.split_last() {
+            Some((&ch, rest)) if piece == Piece::PAWN && ch.is_ascii_alphabetic() => {
+                (Some(piece_of(ch).filter(|&pc| pc != Piece::PAWN)?), rest)
+            }
+            _ => (None, rest),
+        };
+
+        let [hint @ .., file, rank] = rest else {
+            return None;
+        };
+
+        let square = |file: char, rank: char| {
+            let file = u16::from(file as u8).checked_sub(u16::from(b'a'))?;
+            let rank = u16::from(rank as u8).checked_sub(u16::from(b'1'))?;
+            (file < 8 && rank < 8).then_some(8 * rank + file)
+        };
+
+        let to = square(*file, *rank)?;
+        let from_file = hint.iter().find(|ch| ch.is_ascii_lowercase());
+        let from_rank = hint.iter().find(|ch| ch.is_ascii_digit());
+
+        self.map_legal_moves(castling, |mov| {
+            let src = mov.src();
+
+            let matching = ![Flag::KS, Flag::QS].contains(&mov.flag())
+                && mov.to() == to
+                && self.get_pc(1 << src) == piece
+                && promo == mov.is_promo().then(|| mov.promo_pc())
+                && from_file.is_none_or(|&file| file as u16 == u16::from(b'a') + src % 8)
+                && from_rank.is_none_or(|&rank| rank as u16 == u16::from(b'1') + src / 8);
+
+            if matching {
+                found = Some(mov);
+                matches += 1;
+            }
+        });
+
+        found.filter(|_| matches == 1)
+    }
+
     pub fn as_fen(&self) -> String {
         const PIECES: [char; 12] = ['P', 'N', 'B', 'R', 'Q', 'K', 'p', 'n', 'b', 'r', 'q', 'k'];
         let mut fen = String::new();
@@ -1,10 +1,12 @@
 pub mod chess;
 mod format;
 mod interleave;
+mod pgn;
 mod value;
 
 pub use format::{MontyFormat, SearchData};
 pub use interleave::FastDeserialise;
+pub use pgn::{PgnEval, PgnGame, PgnMove, PgnReader};
 pub use value::{MontyValueFormat, SearchResult};
 
 macro_rules! init {
@@ -0,0 +1,803 @@
+use std::{
+    fmt::{Display, Formatter},
+    io::{BufRead, Error, ErrorKind, Write},
+};
+
+use crate::chess::{Castling, Move, Piece, Position, Right, Side, STARTPOS};
+
+type Tags = Vec<(String, String)>;
+
+/// An engine evaluation from a `[%eval]` comment, from white's point of view.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PgnEval {
+    Centipawns(i32),
+    /// Moves to mate, negative if black mates.
+    Mate(i32),
+}
+
+impl PgnEval {
+    /// Parses the argument of a `[%eval]` command, such as `0.35`, `-1.2,18`
+    /// with a search depth, or `#-3`.
+    pub fn parse(eval: &str) -> Option<Self> {
+        let eval = eval.split(',').next()?.trim();
+
+        if let Some(mate) = eval.strip_prefix('#') {
+            mate.parse().ok().map(Self::Mate)
+        } else {
+            let pawns = eval.parse::<f32>().ok().filter(|pawns| pawns.is_finite())?;
+            Some(Self::Centipawns((pawns * 100.0).round() as i32))
+        }
+    }
+}
+
+impl Display for PgnEval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Centipawns(cp) => write!(f, "{:.2}", *cp as f32 / 100.0),
+            Self::Mate(moves) => write!(f, "#{moves}"),
+        }
+    }
+}
+
+pub struct PgnMove {
+    pub mov: Move,
+    /// Numeric annotation glyphs, with `!`, `?` and friends read as `$1` to `$6`.
+    pub nags: Vec<u8>,
+    pub eval: Option<PgnEval>,
+    /// Comment after the move, without its `[%eval]` command.
+    pub comment: Option<String>,
+    /// Alternatives to this move, each starting from the position before it.
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(mov: Move) -> Self {
+        Self {
+            mov,
+            nags: Vec::new(),
+            eval: None,
+            comment: None,
+            variations: Vec::new(),
+        }
+    }
+
+    fn add_comment(&mut self, mut text: &str) {
+        let mut rest = String::new();
+
+        while let Some(start) = text.find("[%eval") {
+            let end = text[start..]
+                .find(']')
+                .map_or(text.len(), |end| start + end);
+
+            if let Some(eval) = PgnEval::parse(&text[start + 6..end]) {
+                self.eval = Some(eval);
+            }
+
+            rest.push_str(&text[..start]);
+            text = &text[(end + 1).min(text.len())..];
+        }
+
+        rest.push_str(text);
+        append_comment(&mut self.comment, &rest);
+    }
+}
+
+/// A game read from or to be written as PGN.
+pub struct PgnGame {
+    /// Tags in the order they were read. When writing, `Result`, `SetUp`
+    /// and `FEN` are taken from the game itself.
+    pub tags: Tags,
+    pub startpos: Position,
+    pub castling: Castling,
+    /// Comment before the first move.
+    pub comment: Option<String>,
+    pub moves: Vec<PgnMove>,
+    /// Result from white's point of view, `None` if unfinished or unknown.
+    pub result: Option<f32>,
+}
+
+impl PgnGame {
+    pub fn new(startpos: Position, castling: Castling) -> Self {
+        Self {
+            tags: Vec::new(),
+            startpos,
+            castling,
+            comment: None,
+            moves: Vec::new(),
+            result: None,
+        }
+    }
+
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        find_tag(&self.tags, key)
+    }
+
+    pub fn set_tag(&mut self, key: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag, _)| tag == key) {
+            Some((_, old)) => *old = value.to_string(),
+            None => self.tags.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    pub fn push(&mut self, mov: PgnMove) {
+        self.moves.push(mov);
+    }
+
+    /// Writes the game followed by the blank line separating it from the next.
+    pub fn serialise_into(&self, writer: &mut impl Write) -> std::io::Result<()> {
+        writeln!(writer, "{self}")
+    }
+
+    fn from_text(tags: Tags, movetext: &str, variations: bool) -> Result<Self, String> {
+        let chess960 = find_tag(&tags, "Variant").is_some_and(is_chess960);
+        let fen = find_tag(&tags, "FEN").filter(|_| find_tag(&tags, "SetUp") != Some("0"));
+
+        let (startpos, castling) = match fen {
+            Some(fen) => parse_fen(fen, chess960).ok_or_else(|| format!("invalid FEN {fen}"))?,
+            None => parse_fen(STARTPOS, chess960).unwrap(),
+        };
+
+        let mut game = Self::new(startpos, castling);
+        game.result = find_tag(&tags, "Result").and_then(parse_result).flatten();
+        game.tags = tags;
+
+        let mut parser = Parser {
+            tokens: tokenise(movetext),
+            idx: 0,
+            castling: game.castling,
+            variations,
+            comment: None,
+            result: None,
+        };
+
+        game.moves = parser.parse_line(game.startpos, false)?;
+        game.comment = parser.comment;
+
+        if let Some(result) = parser.result {
+            game.result = result;
+        }
+
+        Ok(game)
+    }
+}
+
+impl Display for PgnGame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let result = match self.result {
+            Some(result) if result > 0.5 => "1-0",
+            Some(result) if result < 0.5 => "0-1",
+            Some(_) => "1/2-1/2",
+            None => "*",
+        };
+
+        let fen = to_fen(&self.startpos, &self.castling);
+        let chess960 = is_chess960_castling(&self.startpos, &self.castling);
+
+        for (key, value) in &self.tags {
+            match key.as_str() {
+                "FEN" | "SetUp" => {}
+                "Result" => writeln!(f, "[Result \"{result}\"]")?,
+                _ => writeln!(f, "[{key} \"{}\"]", escape(value))?,
+            }
+        }
+
+        if self.tag("Result").is_none() {
+            writeln!(f, "[Result \"{result}\"]")?;
+        }
+
+        if chess960 && self.tag("Variant").is_none() {
+            writeln!(f, "[Variant \"Chess960\"]")?;
+        }
+
+        if fen != STARTPOS {
+            writeln!(f, "[SetUp \"1\"]")?;
+            writeln!(f, "[FEN \"{fen}\"]")?;
+        }
+
+        writeln!(f)?;
+
+        let mut tokens = Vec::new();
+
+        if let Some(comment) = &self.comment {
+            push_comment(&mut tokens, None, comment);
+        }
+
+        write_line(&mut tokens, &self.moves, self.startpos, &self.castling);
+        tokens.push(result.to_string());
+
+        let mut line = String::new();
+
+        for token in tokens {
+            if !line.is_empty() && line.len() + token.len() >= 80 {
+                writeln!(f, "{line}")?;
+                line.clear();
+            }
+
+            if !line.is_empty() {
+                line.push(' ');
+            }
+
+            line.push_str(&token);
+        }
+
+        writeln!(f, "{line}")
+    }
+}
+
+/// Reads games one at a time from PGN text, skipping variations unless
+/// asked to keep them. A game that fails to parse is returned as an error
+/// with kind `InvalidData`, and reading carries on with the next one.
+pub struct PgnReader<R> {
+    reader: R,
+    variations: bool,
+    pending: Option<String>,
+    games: usize,
+}
+
+impl<R: BufRead> PgnReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            variations: false,
+            pending: None,
+            games: 0,
+        }
+    }
+
+    pub fn keep_variations(mut self, keep: bool) -> Self {
+        self.variations = keep;
+        self
+    }
+
+    /// Collects the tags and movetext of the next game, which ends with the
+    /// line holding its result or before the tags of the following game.
+    fn read_game(&mut self) -> std::io::Result<Option<(Tags, String)>> {
+        let mut tags = Vec::new();
+        let mut movetext = String::new();
+        let mut depth = 0;
+
+        loop {
+            let line = match self.pending.take() {
+                Some(line) => line,
+                None => {
+                    let mut line = String::new();
+                    if self.reader.read_line(&mut line)? == 0 {
+                        break;
+                    }
+                    line
+                }
+            };
+
+            let trimmed = line.trim();
+
+            if depth == 0 {
+                if trimmed.starts_with('%') {
+                    continue;
+                }
+
+                if trimmed.starts_with('[') {
+                    if !movetext.trim().is_empty() {
+                        self.pending = Some(line);
+                        break;
+                    }
+
+                    parse_tags(trimmed, &mut tags);
+                    continue;
+                }
+            }
+
+            for ch in trimmed.chars() {
+                match ch {
+                    '}' if depth > 0 => depth -= 1,
+                    '{' => depth += 1,
+                    ';' if depth == 0 => break,
+                    _ => {}
+                }
+            }
+
+            movetext.push_str(trimmed);
+            movetext.push('\n');
+
+            let last = trimmed.split_whitespace().last();
+
+            if depth == 0 && last.and_then(parse_result).is_some() {
+                break;
+            }
+        }
+
+        if tags.is_empty() && movetext.trim().is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some((tags, movetext)))
+    }
+}
+
+impl<R: BufRead> Iterator for PgnReader<R> {
+    type Item = std::io::Result<PgnGame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (tags, movetext) = match self.read_game() {
+            Ok(game) => game?,
+            Err(err) => return Some(Err(err)),
+        };
+
+        self.games += 1;
+
+        Some(
+            PgnGame::from_text(tags, &movetext, self.variations).map_err(|err| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("game {}: {err}", self.games),
+                )
+            }),
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Token<'a> {
+    Move(&'a str),
+    Nag(u8),
+    Comment(&'a str),
+    Open,
+    Close,
+    Result(Option<f32>),
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token<'a>>,
+    idx: usize,
+    castling: Castling,
+    variations: bool,
+    comment: Option<String>,
+    result: Option<Option<f32>>,
+}
+
+impl Parser<'_> {
+    fn parse_line(&mut self, mut pos: Position, nested: bool) -> Result<Vec<PgnMove>, String> {
+        let mut moves: Vec<PgnMove> = Vec::new();
+        let mut before = pos;
+
+        while let Some(&token) = self.tokens.get(self.idx) {
+            self.idx += 1;
+
+            match token {
+                Token::Move(san) => {
+                    let Some(mov) = pos.parse_san(san, &self.castling) else {
+                        let dots = if pos.stm() == Side::WHITE { "." } else { "..." };
+                        return Err(format!("illegal move {}{dots}{san}", pos.fullm()));
+                    };
+
+                    before = pos;
+                    pos.make(mov, &self.castling);
+                    moves.push(PgnMove::new(mov));
+                }
+                Token::Nag(nag) => {
+                    if let Some(last) = moves.last_mut() {
+                        last.nags.push(nag);
+                    }
+                }
+                Token::Comment(text) => match moves.last_mut() {
+                    Some(last) => last.add_comment(text),
+                    None if !nested => append_comment(&mut self.comment, text),
+                    None => {}
+                },
+                Token::Open => {
+                    let last = moves.last_mut().ok_or("variation before the first move")?;
+
+                    if self.variations {
+                        let variation = self.parse_line(before, true)?;
+
+                        if !variation.is_empty() {
+                            last.variations.push(variation);
+                        }
+                    } else {
+                        self.skip_variation()?;
+                    }
+                }
+                Token::Close if nested => return Ok(moves),
+                Token::Close => return Err("unmatched ')'".to_string()),
+                Token::Result(result) => {
+                    if !nested {
+                        self.result = Some(result);
+                        return Ok(moves);
+                    }
+                }
+            }
+        }
+
+        if nested {
+            Err("unterminated variation".to_string())
+        } else {
+            Ok(moves)
+        }
+    }
+
+    fn skip_variation(&mut self) -> Result<(), String> {
+        let mut depth = 1;
+
+        while let Some(&token) = self.tokens.get(self.idx) {
+            self.idx += 1;
+
+            match token {
+                Token::Open => depth += 1,
+                Token::Close if depth == 1 => return Ok(()),
+                Token::Close => depth -= 1,
+                _ => {}
+            }
+        }
+
+        Err("unterminated variation".to_string())
+    }
+}
+
+fn tokenise(movetext: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut rest = movetext;
+
+    loop {
+        rest = rest.trim_start();
+
+        let Some(ch) = rest.chars().next() else {
+            break;
+        };
+
+        match ch {
+            '{' | ';' => {
+                let close = if ch == '{' { '}' } else { '\n' };
+                let end = rest.find(close).unwrap_or(rest.len());
+                tokens.push(Token::Comment(rest[1..end].trim()));
+                rest = &rest[(end + 1).min(rest.len())..];
+            }
+            '(' | ')' => {
+                tokens.push(if ch == '(' { Token::Open } else { Token::Close });
+                rest = &rest[1..];
+            }
+            '$' => {
+                let end = rest[1..]
+                    .find(|ch: char| !ch.is_ascii_digit())
+                    .map_or(rest.len(), |end| end + 1);
+
+                if let Ok(nag) = rest[1..end].parse() {
+                    tokens.push(Token::Nag(nag));
+                }
+
+                rest = &rest[end..];
+            }
+            _ => {
+                let end = rest
+                    .find(|ch: char| ch.is_whitespace() || "{};()$".contains(ch))
+                    .unwrap_or(rest.len());
+
+                push_word(&mut tokens, &rest[..end]);
+                rest = &rest[end..];
+            }
+        }
+    }
+
+    tokens
+}
+
+fn push_word<'a>(tokens: &mut Vec<Token<'a>>, mut word: &'a str) {
+    if let Some(result) = parse_result(word) {
+        tokens.push(Token::Result(result));
+        return;
+    }
+
+    // move numbers, which may be joined to the move after them
+    let digits = word.len()
+        - word
+            .trim_start_matches(|ch: char| ch.is_ascii_digit())
+            .len();
+
+    if word[digits..].starts_with('.') {
+        word = &word[digits..];
+    }
+
+    word = word.trim_start_matches('.');
+
+    let san = word.trim_end_matches(['!', '?']);
+
+    if !san.is_empty() {
+        tokens.push(Token::Move(san));
+    }
+
+    let nag = match &word[san.len()..] {
+        "!" => 1,
+        "?" => 2,
+        "!!" => 3,
+        "??" => 4,
+        "!?" => 5,
+        "?!" => 6,
+        _ => return,
+    };
+
+    tokens.push(Token::Nag(nag));
+}
+
+fn parse_result(token: &str) -> Option<Option<f32>> {
+    match token {
+        "1-0" => Some(Some(1.0)),
+        "0-1" => Some(Some(0.0)),
+        "1/2-1/2" | "½-½" => Some(Some(0.5)),
+        "*" => Some(None),
+        _ => None,
+    }
+}
+
+fn parse_tags(line: &str, tags: &mut Tags) {
+    let mut rest = line;
+
+    while let Some(start) = rest.find('[') {
+        rest = rest[start + 1..].trim_start();
+
+        let key_end = rest
+            .find(|ch: char| ch.is_whitespace() || ch == '"')
+            .unwrap_or(rest.len());
+        let key = &rest[..key_end];
+        rest = rest[key_end..].trim_start();
+
+        let Some(quoted) = rest.strip_prefix('"') else {
+            continue;
+        };
+
+        let mut value = String::new();
+        let mut chars = quoted.char_indices();
+        let mut end = quoted.len();
+
+        while let Some((i, ch)) = chars.next() {
+            match ch {
+                '\\' => value.extend(chars.next().map(|(_, ch)| ch)),
+                '"' => {
+                    end = i + 1;
+                    break;
+                }
+                _ => value.push(ch),
+            }
+        }
+
+        if !key.is_empty() {
+            tags.push((key.to_string(), value));
+        }
+
+        rest = &quoted[end..];
+    }
+}
+
+fn find_tag<'a>(tags: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    tags.iter()
+        .find(|(tag, _)| tag.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value.as_str())
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn append_comment(comment: &mut Option<String>, text: &str) {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if text.is_empty() {
+        return;
+    }
+
+    match comment {
+        Some(comment) => {
+            comment.push(' ');
+            comment.push_str(&text);
+        }
+        None => *comment = Some(text),
+    }
+}
+
+fn is_chess960(variant: &str) -> bool {
+    let variant = variant.to_lowercase();
+    variant.contains("960") || variant.contains("fischer") || variant.contains("freestyle")
+}
+
+/// Parses a FEN, checking it first, as a Chess960 position if asked, where
+/// `KQkq` castling rights refer to the outermost rooks.
+fn parse_fen(fen: &str, chess960: bool) -> Option<(Position, Castling)> {
+    let fields = fen.split_whitespace().collect::<Vec<_>>();
+
+    if fields.len() < 4 || !is_valid_board(fields[0]) || !["w", "b"].contains(&fields[1]) {
+        return None;
+    }
+
+    let halfm = fields.get(4).copied().unwrap_or("0");
+    let fullm = fields.get(5).copied().unwrap_or("1");
+
+    if halfm.parse::<u8>().is_err() || fullm.parse::<u16>().is_err() {
+        return None;
+    }
+
+    let rights = fields[2];
+
+    if rights != "-" && !rights.chars().all(|ch| "KQkqABCDEFGHabcdefgh".contains(ch)) {
+        return None;
+    }
+
+    let enp = fields[3].as_bytes();
+
+    if fields[3] != "-"
+        && !(enp.len() == 2 && (b'a'..=b'h').contains(&enp[0]) && b"36".contains(&enp[1]))
+    {
+        return None;
+    }
+
+    let mut castling = Castling::default();
+
+    let rights = if chess960 {
+        let pos = Position::parse_fen(
+            &format!("{} {} - - 0 1", fields[0], fields[1]),
+            &mut castling,
+        );
+        rights.chars().map(|ch| outer_rook(&pos, ch)).collect()
+    } else {
+        rights.to_string()
+    };
+
+    let fen = format!(
+        "{} {} {rights} {} {halfm} {fullm}",
+        fields[0], fields[1], fields[3]
+    );
+    let pos = Position::parse_fen(&fen, &mut castling);
+
+    Some((pos, castling))
+}
+
+fn is_valid_board(board: &str) -> bool {
+    let ranks = board.split('/').collect::<Vec<_>>();
+
+    let valid_rank = |rank: &&str| {
+        let mut squares = 0;
+
+        for ch in rank.chars() {
+            squares += match ch {
+                '1'..='8' => ch as u32 - '0' as u32,
+                'P' | 'N' | 'B' | 'R' | 'Q' | 'K' | 'p' | 'n' | 'b' | 'r' | 'q' | 'k' => 1,
+                _ => return false,
+            };
+        }
+
+        squares == 8
+    };
+
+    ranks.len() == 8
+        && ranks.iter().all(valid_rank)
+        && board.matches('K').count() == 1
+        && board.matches('k').count() == 1
+}
+
+/// Turns a `KQkq` castling right into the file of the outermost rook on
+/// that side of the king, as Shredder-FEN does.
+fn outer_rook(pos: &Position, right: char) -> char {
+    let side = usize::from(right.is_ascii_lowercase());
+    let king = pos.king_sq(side) as u8 & 7;
+    let rooks = pos.piece(side) & pos.piece(Piece::ROOK) & (0xFF << (56 * side));
+
+    let files = (0..8u8).filter(|&file| rooks & (1 << (56 * side + usize::from(file))) > 0);
+
+    let file = match right.to_ascii_uppercase() {
+        'K' => files.filter(|&file| file > king).max(),
+        'Q' => files.filter(|&file| file < king).min(),
+        _ => return right,
+    };
+
+    file.map_or(right, |file| (b"Aa"[side] + file) as char)
+}
+
+/// Whether castling needs Chess960 rules, which is not always flagged for
+/// positions read from binpacks.
+fn is_chess960_castling(pos: &Position, castling: &Castling) -> bool {
+    let rights = [
+        (Right::WKS, 0, 1),
+        (Right::WQS, 0, 0),
+        (Right::BKS, 1, 1),
+        (Right::BQS, 1, 0),
+    ];
+
+    castling.is_chess960()
+        || rights.iter().any(|&(right, side, ks)| {
+            pos.rights() & right > 0
+                && (castling.rook_file(side, ks) != [0, 7][ks]
+                    || pos.king_sq(side) != 4 + 56 * side)
+        })
+}
+
+/// Writes a FEN with castling rights as the files of the rooks in Chess960,
+/// and the en passant square if there is one.
+fn to_fen(pos: &Position, castling: &Castling) -> String {
+    let chess960 = is_chess960_castling(pos, castling);
+
+    let fen = pos.as_fen();
+    let fields = fen.split_whitespace().collect::<Vec<_>>();
+
+    let mut rights = String::new();
+
+    for (right, side, ks, ch) in [
+        (Right::WKS, 0, 1, 'K'),
+        (Right::WQS, 0, 0, 'Q'),
+        (Right::BKS, 1, 1, 'k'),
+        (Right::BQS, 1, 0, 'q'),
+    ] {
+        if pos.rights() & right > 0 {
+            rights.push(if chess960 {
+                (b"Aa"[side] + castling.rook_file(side, ks) as u8) as char
+            } else {
+                ch
+            });
+        }
+    }
+
+    if rights.is_empty() {
+        rights.push('-');
+    }
+
+    let enp = if pos.enp_sq() > 0 {
+        format!(
+            "{}{}",
+            (b'a' + pos.enp_sq() % 8) as char,
+            pos.enp_sq() / 8 + 1
+        )
+    } else {
+        "-".to_string()
+    };
+
+    format!(
+        "{} {} {rights} {enp} {} {}",
+        fields[0],
+        fields[1],
+        pos.halfm(),
+        pos.fullm()
+    )
+}
+
+fn push_comment(tokens: &mut Vec<String>, eval: Option<PgnEval>, comment: &str) {
+    let eval = eval.map(|eval| format!("[%eval {eval}]"));
+    let text = [eval.as_deref(), Some(comment)]
+        .into_iter()
+        .flatten()
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    tokens.push(format!("{{{text}}}"));
+}
+
+fn write_line(tokens: &mut Vec<String>, moves: &[PgnMove], mut pos: Position, castling: &Castling) {
+    let mut number = true;
+
+    for mov in moves {
+        let san = mov.mov.to_san(&pos, castling);
+
+        // numbers are kept on the same line as their move
+        tokens.push(if pos.stm() == Side::WHITE {
+            format!("{}. {san}", pos.fullm())
+        } else if number {
+            format!("{}... {san}", pos.fullm())
+        } else {
+            san
+        });
+        tokens.extend(mov.nags.iter().map(|nag| format!("${nag}")));
+
+        if mov.eval.is_some() || mov.comment.is_some() {
+            let comment = mov.comment.as_deref().unwrap_or_default();
+            push_comment(tokens, mov.eval, comment);
+        }
+
+        for variation in &mov.variations {
+            let start = tokens.len();
+            write_line(tokens, variation, pos, castling);
+
+            if tokens.len() > start {
+                tokens[start].insert(0, '(');
+                tokens.last_mut().unwrap().push(')');
+            }
+        }
+
+        number = mov.eval.is_some() || mov.comment.is_some() || !mov.variations.is_empty();
+        pos.make(mov.mov, castling);
+    }
+}
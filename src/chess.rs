@@ -1,10 +1,15 @@
 use crate::{
     mcts::MctsParams,
-    networks::{Accumulator, PolicyNetwork, ValueNetwork, POLICY_L1},
+    networks::{
+        policy::see::{self, SEE_VALS},
+        Accumulator, PolicyNetwork, ValueNetwork, POLICY_L1,
+    },
 };
 
 pub use montyformat::chess::{Attacks, Castling, GameState, Move, Position};
 
+use montyformat::chess::consts::Piece;
+
 #[derive(Clone, Copy, Debug)]
 pub struct EvalWdl {
     pub win: f32,
@@ -37,7 +42,12 @@ impl EvalWdl {
     }
 
     pub fn score(&self) -> f32 {
-        self.win + 0.5 * self.draw
+        self.score_with_draw(0.5)
+    }
+
+    /// Expected score when a draw is worth `draw` instead of a half point.
+    pub fn score_with_draw(&self, draw: f32) -> f32 {
+        self.win + draw * self.draw
     }
 
     pub fn from_draw_and_score(draw: f32, score: f32) -> Self {
@@ -107,11 +117,15 @@ pub struct EvalBreakdown {
     pub cp: i32,
 }
 
+/// Piece moved and its destination square.
+pub type PieceTo = (usize, usize);
+
 #[derive(Clone)]
 pub struct ChessState {
     board: Position,
     castling: Castling,
     stack: Vec<u64>,
+    recent: [Option<PieceTo>; 2],
 }
 
 impl Default for ChessState {
@@ -122,6 +136,7 @@ impl Default for ChessState {
 
 impl ChessState {
     pub const STARTPOS: &'static str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+    pub const MAX_PHASE: i32 = 24;
     #[cfg(feature = "datagen")]
     pub const BENCH_DEPTH: usize = 4;
 
@@ -148,7 +163,50 @@ impl ChessState {
             board,
             castling,
             stack: Vec::new(),
+            recent: [None; 2],
+        }
+    }
+
+    /// Whether `fen` is well formed enough for [`Self::from_fen`].
+    pub fn is_valid_fen(fen: &str) -> bool {
+        let fields = fen.split_whitespace().collect::<Vec<_>>();
+
+        if fields.len() != 6 {
+            return false;
         }
+
+        let ranks = fields[0].split('/').collect::<Vec<_>>();
+
+        let valid_rank = |rank: &&str| {
+            let mut squares = 0;
+
+            for ch in rank.chars() {
+                match ch {
+                    '1'..='8' => squares += ch as u32 - '0' as u32,
+                    'p' | 'n' | 'b' | 'r' | 'q' | 'k' | 'P' | 'N' | 'B' | 'R' | 'Q' | 'K' => {
+                        squares += 1
+                    }
+                    _ => return false,
+                }
+            }
+
+            squares == 8
+        };
+
+        let kings = |king| fields[0].chars().filter(|&ch| ch == king).count() == 1;
+
+        let valid_enp =
+            fields[3] == "-" || matches!(fields[3].as_bytes(), [b'a'..=b'h', b'3' | b'6']);
+
+        ranks.len() == 8
+            && ranks.iter().all(valid_rank)
+            && kings('K')
+            && kings('k')
+            && matches!(fields[1], "w" | "b")
+            && fields[2]
+                .chars()
+                .all(|ch| matches!(ch, 'K' | 'Q' | 'k' | 'q' | 'A'..='H' | 'a'..='h' | '-'))
+            && valid_enp
     }
 
     pub fn map_legal_moves<F: FnMut(Move)>(&self, f: F) {
@@ -164,6 +222,9 @@ impl ChessState {
     }
 
     pub fn make_move(&mut self, mov: Move) {
+        let piece = self.board.get_pc(1 << mov.src());
+        self.recent = [Some((piece, usize::from(mov.to()))), self.recent[0]];
+
         self.stack.push(self.board.hash());
         self.board.make(mov, &self.castling);
 
@@ -176,6 +237,11 @@ impl ChessState {
         self.board.stm()
     }
 
+    /// The last move played and the one before it, if known.
+    pub fn recent_moves(&self) -> [Option<PieceTo>; 2] {
+        self.recent
+    }
+
     pub fn map_moves_with_policies<F: FnMut(Move, f32)>(&self, policy: &PolicyNetwork, mut f: F) {
         let hl = policy.hl(&self.board);
 
@@ -198,11 +264,78 @@ impl ChessState {
         policy.get(&self.board, &mov, hl)
     }
 
-    #[cfg(not(feature = "datagen"))]
-    fn piece_count(&self, piece: usize) -> i32 {
+    /// Finds the legal move written as `san` in standard algebraic
+    /// notation, see [`Position::parse_san`] for the forms accepted.
+    pub fn parse_san(&self, san: &str) -> Option<Move> {
+        self.board.parse_san(san, &self.castling)
+    }
+
+    /// Writes a legal move in standard algebraic notation.
+    pub fn conv_mov_to_san(&self, mov: Move) -> String {
+        mov.to_san(&self.board, &self.castling)
+    }
+
+    /// Plays a move given in UCI notation.
+    pub fn make_uci_move(&mut self, mov: &str) {
+        let mut this_mov = Move::default();
+
+        self.map_legal_moves(|legal| {
+            if mov == self.conv_mov_to_str(legal) {
+                this_mov = legal;
+            }
+        });
+
+        self.make_move(this_mov);
+    }
+
+    pub fn is_legal_uci_move(&self, mov: &str) -> bool {
+        let mut legal = false;
+        self.map_legal_moves(|m| legal |= self.conv_mov_to_str(m) == mov);
+        legal
+    }
+
+    /// Plays `moves` in UCI notation from `fen`, or reports the invalid
+    /// FEN or first illegal move.
+    pub fn from_fen_and_moves(fen: &str, moves: &[&str]) -> Result<Self, String> {
+        if !Self::is_valid_fen(fen) {
+            return Err(format!("invalid fen: {}", fen.trim()));
+        }
+
+        let mut pos = Self::from_fen(fen);
+
+        for &mov in moves {
+            let mut legal = None;
+            pos.map_legal_moves(|m| {
+                if pos.conv_mov_to_str(m) == mov {
+                    legal = Some(m);
+                }
+            });
+
+            let Some(legal) = legal else {
+                return Err(format!("illegal move: {mov}"));
+            };
+
+            pos.make_move(legal);
+        }
+
+        Ok(pos)
+    }
+
+    pub fn piece_count(&self, piece: usize) -> i32 {
         self.board.piece(piece).count_ones() as i32
     }
 
+    /// Non-pawn material, from 0 in pawn endgames up
+    /// to [`Self::MAX_PHASE`] in the starting position.
+    pub fn phase(&self) -> i32 {
+        let phase = self.piece_count(Piece::KNIGHT)
+            + self.piece_count(Piece::BISHOP)
+            + 2 * self.piece_count(Piece::ROOK)
+            + 4 * self.piece_count(Piece::QUEEN);
+
+        phase.min(Self::MAX_PHASE)
+    }
+
     fn evaluate_material_wdl(
         &self,
         value: &ValueNetwork,
@@ -214,8 +347,6 @@ impl ChessState {
 
         #[cfg(not(feature = "datagen"))]
         let cp = {
-            use montyformat::chess::consts::Piece;
-
             let mut mat = self.piece_count(Piece::KNIGHT) * params.knight_value()
                 + self.piece_count(Piece::BISHOP) * params.bishop_value()
                 + self.piece_count(Piece::ROOK) * params.rook_value()
@@ -265,7 +396,26 @@ impl ChessState {
     pub fn get_value_wdl(&self, value: &ValueNetwork, params: &MctsParams, root_stm: usize) -> f32 {
         self.eval_with_contempt(value, params, root_stm)
             .contempt
-            .score()
+            .score_with_draw(params.draw_utility(self.stm(), root_stm))
+    }
+
+    /// Material the side to move can win by force, found by a capture-only
+    /// alpha-beta search of at most `budget` nodes. Captures that lose
+    /// material by SEE are not searched.
+    pub fn tactical_gain(&self, budget: usize) -> i32 {
+        if self.board.in_check() {
+            return 0;
+        }
+
+        let mut nodes = 0;
+        qsearch(
+            &self.board,
+            &self.castling,
+            -i32::MAX,
+            i32::MAX,
+            &mut nodes,
+            budget,
+        )
     }
 
     pub fn perft(&self, depth: usize) -> u64 {
@@ -344,6 +494,67 @@ impl ChessState {
     }
 }
 
+fn qsearch(
+    pos: &Position,
+    castling: &Castling,
+    mut alpha: i32,
+    beta: i32,
+    nodes: &mut usize,
+    budget: usize,
+) -> i32 {
+    // standing pat, scores are relative to the current material
+    alpha = alpha.max(0);
+
+    if alpha >= beta || *nodes >= budget {
+        return alpha;
+    }
+
+    let mut captures = Vec::new();
+    pos.map_legal_captures(castling, |mov| {
+        if see::greater_or_equal_to(pos, &mov, 0) {
+            let captured = if mov.is_en_passant() {
+                SEE_VALS[Piece::PAWN]
+            } else {
+                SEE_VALS[pos.get_pc(1 << mov.to())]
+            };
+
+            let promoted = if mov.is_promo() {
+                SEE_VALS[mov.promo_pc()] - SEE_VALS[Piece::PAWN]
+            } else {
+                0
+            };
+
+            captures.push((mov, captured + promoted));
+        }
+    });
+
+    // most valuable victim first
+    captures.sort_by_key(|&(_, gain)| -gain);
+
+    for (mov, gain) in captures {
+        if *nodes >= budget {
+            break;
+        }
+
+        *nodes += 1;
+
+        let mut tmp = *pos;
+        tmp.make(mov, castling);
+
+        let score = gain - qsearch(&tmp, castling, gain - beta, gain - alpha, nodes, budget);
+
+        if score > alpha {
+            alpha = score;
+
+            if alpha >= beta {
+                break;
+            }
+        }
+    }
+
+    alpha
+}
+
 fn perft<const ROOT: bool, const BULK: bool>(
     pos: &Position,
     depth: u8,
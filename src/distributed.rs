@@ -0,0 +1,550 @@
+use std::{
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{Shutdown, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    chess::{ChessState, GameState, Move},
+    mcts::{
+        Limits, MctsParams, RemoteSearch, Reporter, RootChildStats, RootNoise, SearchEvent,
+        Searcher,
+    },
+    networks::{PolicyNetwork, ValueNetwork},
+    tree::Tree,
+    uci,
+};
+
+// Searches are coordinated with a line based protocol. The coordinator
+// sends the `position` command it received over UCI and its search
+// parameters, in the units of `MctsParams::set`, followed by
+//
+//     params <name>=<value> ...
+//     go <id> <max_time> <opt_time> <max_nodes> <max_depth> <noise> <seed>
+//
+// with `-` for a missing limit, and `stop` once it is done. The worker
+// replies with the statistics of its root children every `STATS_INTERVAL`
+//
+//     stats <id> <nodes> <move>:<visits>:<q>:<state> ...
+//
+// with moves and game states in their `u16` encoding, and sends `done <id>`
+// after a final `stats` line once it has received `stop`. Replies tagged
+// with the id of an earlier search arrived too late, and are dropped.
+
+const STATS_INTERVAL: Duration = Duration::from_millis(100);
+const FINISH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Connections to worker processes that search alongside this one, see
+/// [`serve`]. Workers run root-parallel with differently seeded noise.
+#[derive(Default)]
+pub struct Workers {
+    connections: Vec<Connection>,
+    position: String,
+    search_id: u64,
+}
+
+struct Connection {
+    addr: String,
+    stream: TcpStream,
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    state: Mutex<WorkerState>,
+    done: Condvar,
+}
+
+#[derive(Default)]
+struct WorkerState {
+    search_id: u64,
+    children: Vec<RootChildStats>,
+    nodes: usize,
+    done: bool,
+    disconnected: bool,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.stream.shutdown(Shutdown::Both);
+    }
+}
+
+impl Connection {
+    fn open(addr: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+
+        let shared = Arc::new(Shared::default());
+        let reader = BufReader::new(stream.try_clone()?);
+        let reader_shared = shared.clone();
+
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+
+                let mut tokens = line.split_whitespace();
+
+                let (Some(reply), Some(Ok(search_id))) =
+                    (tokens.next(), tokens.next().map(str::parse::<u64>))
+                else {
+                    continue;
+                };
+
+                let mut state = reader_shared.state.lock().unwrap();
+
+                if search_id != state.search_id {
+                    continue;
+                }
+
+                if reply == "done" {
+                    state.done = true;
+                    reader_shared.done.notify_all();
+                } else if let Some((children, nodes)) =
+                    (reply == "stats").then(|| parse_stats(tokens)).flatten()
+                {
+                    state.children = children;
+                    state.nodes = nodes;
+                }
+            }
+
+            let mut state = reader_shared.state.lock().unwrap();
+            state.done = true;
+            state.disconnected = true;
+            reader_shared.done.notify_all();
+        });
+
+        Ok(Self {
+            addr: addr.to_string(),
+            stream,
+            shared,
+        })
+    }
+
+    fn send(&mut self, line: &str) -> io::Result<()> {
+        writeln!(self.stream, "{line}")
+    }
+}
+
+impl Workers {
+    pub fn connect(&mut self, addr: &str) -> io::Result<()> {
+        self.connections.push(Connection::open(addr)?);
+        Ok(())
+    }
+
+    pub fn disconnect_all(&mut self) {
+        self.connections.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.connections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+
+    /// Remembers the `position` command to send with the next search.
+    pub fn set_position(&mut self, command: &str) {
+        self.position = command.to_string();
+    }
+
+    pub fn new_game(&mut self, reporter: &Reporter) {
+        self.send_all("ucinewgame", reporter);
+    }
+
+    fn send_all(&mut self, line: &str, reporter: &Reporter) {
+        self.connections.retain_mut(|connection| {
+            let disconnected = connection.shared.state.lock().unwrap().disconnected;
+            let result = if disconnected {
+                Err(io::Error::from(ErrorKind::NotConnected))
+            } else {
+                connection.send(line)
+            };
+
+            if let Err(err) = &result {
+                reporter(SearchEvent::Message(format!(
+                    "lost worker {}: {err}",
+                    connection.addr
+                )));
+            }
+
+            result.is_ok()
+        });
+    }
+
+    fn collect(&self) -> (Vec<RootChildStats>, usize) {
+        let mut children = Vec::new();
+        let mut nodes = 0;
+
+        for connection in &self.connections {
+            let state = connection.shared.state.lock().unwrap();
+            children.extend_from_slice(&state.children);
+            nodes += state.nodes;
+        }
+
+        (children, nodes)
+    }
+}
+
+impl RemoteSearch for Workers {
+    fn searches(&self) -> usize {
+        self.connections.len()
+    }
+
+    fn start(
+        &mut self,
+        params: &MctsParams,
+        limits: &Limits,
+        noise: f32,
+        seed: u64,
+        reporter: &Reporter,
+    ) {
+        self.search_id += 1;
+
+        for connection in &self.connections {
+            let mut state = connection.shared.state.lock().unwrap();
+            state.search_id = self.search_id;
+            state.children.clear();
+            state.nodes = 0;
+            state.done = false;
+        }
+
+        let position = if self.position.is_empty() {
+            "position startpos".to_string()
+        } else {
+            self.position.clone()
+        };
+
+        self.send_all(&position, reporter);
+
+        let mut params_line = "params".to_string();
+
+        for (name, value) in params.values() {
+            write!(params_line, " {name}={value}").unwrap();
+        }
+
+        self.send_all(&params_line, reporter);
+
+        let limit = |limit: Option<u128>| limit.map_or("-".to_string(), |x| x.to_string());
+        let max_nodes = Some(limits.max_nodes as u128).filter(|&x| x != usize::MAX as u128);
+
+        for (i, connection) in self.connections.iter_mut().enumerate() {
+            let go = format!(
+                "go {} {} {} {} {} {noise} {}",
+                self.search_id,
+                limit(limits.max_time),
+                limit(limits.opt_time),
+                limit(max_nodes),
+                limits.max_depth,
+                seed.wrapping_add(i as u64),
+            );
+
+            // a lost connection is noticed by its reader thread
+            let _ = connection.send(&go);
+        }
+    }
+
+    fn stats(&self) -> (Vec<RootChildStats>, usize) {
+        self.collect()
+    }
+
+    fn finish(&mut self, reporter: &Reporter) -> (Vec<RootChildStats>, usize) {
+        self.send_all("stop", reporter);
+
+        let deadline = Instant::now() + FINISH_TIMEOUT;
+
+        for connection in &self.connections {
+            let state = connection.shared.state.lock().unwrap();
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            let (state, _) = connection
+                .shared
+                .done
+                .wait_timeout_while(state, timeout, |state| !state.done)
+                .unwrap();
+
+            if !state.done {
+                reporter(SearchEvent::Message(format!(
+                    "worker {} did not finish in time",
+                    connection.addr
+                )));
+            }
+        }
+
+        self.collect()
+    }
+}
+
+/// Searches positions sent by coordinators connecting to `addr`, one
+/// coordinator at a time.
+pub fn serve(
+    addr: &str,
+    policy: &PolicyNetwork,
+    value: &ValueNetwork,
+    threads: usize,
+    hash_mb: usize,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("info string worker listening on {}", listener.local_addr()?);
+
+    let mut tree = Tree::new_mb(hash_mb, threads);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                println!("info string failed to accept coordinator: {err}");
+                continue;
+            }
+        };
+
+        let peer = stream.peer_addr()?;
+        println!("info string coordinator {peer} connected");
+
+        let worker = Worker {
+            policy,
+            value,
+            threads,
+        };
+
+        match worker.serve_connection(stream, &mut tree) {
+            Ok(()) => println!("info string coordinator {peer} disconnected"),
+            Err(err) => println!("info string coordinator {peer} disconnected: {err}"),
+        }
+    }
+
+    Ok(())
+}
+
+struct Worker<'a> {
+    policy: &'a PolicyNetwork,
+    value: &'a ValueNetwork,
+    threads: usize,
+}
+
+impl Worker<'_> {
+    fn serve_connection(&self, stream: TcpStream, tree: &mut Tree) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let writer = Mutex::new(stream);
+        let mut pos = ChessState::default();
+        let mut params = MctsParams::default();
+        let mut line = String::new();
+
+        loop {
+            line.clear();
+
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+
+            let commands = line.split_whitespace().collect::<Vec<_>>();
+
+            let invalid = || io::Error::new(ErrorKind::InvalidData, line.trim().to_string());
+
+            match commands.first() {
+                Some(&"position") => {
+                    pos = uci::checked_position(&commands)
+                        .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+                }
+                Some(&"params") => params = parse_params(&commands).ok_or_else(invalid)?,
+                Some(&"ucinewgame") => tree.clear(self.threads),
+                Some(&"go") => {
+                    let (search_id, limits) = parse_go(&commands).ok_or_else(invalid)?;
+
+                    tree.set_root_position(&pos);
+                    self.search(&mut reader, &writer, tree, &params, search_id, limits)?;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn search(
+        &self,
+        reader: &mut BufReader<TcpStream>,
+        writer: &Mutex<TcpStream>,
+        tree: &Tree,
+        params: &MctsParams,
+        search_id: u64,
+        limits: Limits,
+    ) -> io::Result<()> {
+        let abort = AtomicBool::new(false);
+        let finished = AtomicBool::new(false);
+        let searcher = Searcher::new(tree, params, self.policy, self.value, &abort, None);
+        let start_visits = root_visits(tree);
+        let mut nodes = 0;
+
+        thread::scope(|s| {
+            s.spawn(|| {
+                searcher.search(
+                    self.threads,
+                    limits,
+                    false,
+                    &mut nodes,
+                    #[cfg(feature = "datagen")]
+                    false,
+                    #[cfg(feature = "datagen")]
+                    1.0,
+                );
+
+                finished.store(true, Ordering::Relaxed);
+            });
+
+            s.spawn(|| {
+                while !finished.load(Ordering::Relaxed) {
+                    thread::sleep(STATS_INTERVAL);
+
+                    // exact node counts are only known once the search
+                    // finishes, so root visits stand in for them until then
+                    let iters = root_visits(tree).saturating_sub(start_visits);
+
+                    // write errors are noticed by the reader instead
+                    let _ = send_stats(writer, tree, search_id, iters as usize);
+                }
+            });
+
+            let mut line = String::new();
+
+            let result = loop {
+                line.clear();
+
+                match reader.read_line(&mut line) {
+                    Ok(0) => break Err(io::Error::from(ErrorKind::UnexpectedEof)),
+                    Ok(_) if line.trim() == "stop" => break Ok(()),
+                    Ok(_) => {}
+                    Err(err) => break Err(err),
+                }
+            };
+
+            abort.store(true, Ordering::Relaxed);
+            result
+        })?;
+
+        send_stats(writer, tree, search_id, nodes)?;
+        writeln!(writer.lock().unwrap(), "done {search_id}")
+    }
+}
+
+fn root_visits(tree: &Tree) -> u64 {
+    if tree.is_empty() {
+        0
+    } else {
+        tree[tree.root_node()].visits()
+    }
+}
+
+fn send_stats(
+    writer: &Mutex<TcpStream>,
+    tree: &Tree,
+    search_id: u64,
+    nodes: usize,
+) -> io::Result<()> {
+    let mut line = format!("stats {search_id} {nodes}");
+
+    if !tree.is_empty() {
+        let node = &tree[tree.root_node()];
+        let first_child_ptr = node.actions();
+
+        for action in 0..node.num_actions() {
+            let child = &tree[first_child_ptr + action];
+
+            write!(
+                line,
+                " {}:{}:{}:{}",
+                u16::from(child.parent_move()),
+                child.visits(),
+                child.q(),
+                u16::from(child.state()),
+            )
+            .unwrap();
+        }
+    }
+
+    line.push('\n');
+    writer.lock().unwrap().write_all(line.as_bytes())
+}
+
+fn parse_stats<'a>(
+    mut tokens: impl Iterator<Item = &'a str>,
+) -> Option<(Vec<RootChildStats>, usize)> {
+    let nodes = tokens.next()?.parse().ok()?;
+
+    let children = tokens
+        .map(|token| {
+            let mut fields = token.split(':');
+
+            let mov = Move::from(fields.next()?.parse::<u16>().ok()?);
+            let visits = fields.next()?.parse().ok()?;
+            let q = fields.next()?.parse().ok()?;
+            let state = GameState::from(fields.next()?.parse::<u16>().ok()?);
+
+            Some(RootChildStats {
+                mov,
+                visits,
+                q,
+                state,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    Some((children, nodes))
+}
+
+fn parse_params(commands: &[&str]) -> Option<MctsParams> {
+    let mut params = MctsParams::default();
+
+    for token in &commands[1..] {
+        let (name, value) = token.split_once('=')?;
+        if !params.set(name, value.parse().ok()?) {
+            return None;
+        }
+    }
+
+    Some(params)
+}
+
+fn parse_go(commands: &[&str]) -> Option<(u64, Limits)> {
+    let [_, search_id, max_time, opt_time, max_nodes, max_depth, noise, seed] = commands else {
+        return None;
+    };
+
+    let limit = |token: &str| -> Option<Option<u128>> {
+        match token {
+            "-" => Some(None),
+            _ => token.parse().ok().map(Some),
+        }
+    };
+
+    let noise = noise.parse::<f32>().ok()?;
+
+    let limits = Limits {
+        max_time: limit(max_time)?,
+        opt_time: limit(opt_time)?,
+        max_depth: max_depth.parse().ok()?,
+        max_nodes: limit(max_nodes)?.map_or(usize::MAX, |nodes| nodes as usize),
+        gumbel: false,
+        deterministic: false,
+        root_noise: Some(RootNoise {
+            alpha: 1.0,
+            epsilon: noise,
+            seed: seed.parse().ok()?,
+        })
+        .filter(|noise| noise.epsilon > 0.0),
+        move_temperature: None,
+        #[cfg(feature = "datagen")]
+        kld_min_gain: None,
+    };
+
+    Some((search_id.parse().ok()?, limits))
+}
@@ -0,0 +1,980 @@
+use crate::{
+    chess::{ChessState, EvalBreakdown, EvalWdl, Move},
+    distributed::Workers,
+    experience::Experience,
+    mcts::{
+        search_root_parallel, Limits, MctsParams, MoveTemperature, RemoteSearch, Reporter,
+        RootMove, RootNoise, Score, SearchEvent, SearchHelpers, Searcher, Strength, JSON_OUTPUT,
+        MAX_ELO, MIN_ELO, REPORT_ITERS,
+    },
+    networks::{PolicyNetwork, ValueNetwork},
+    tablebase::Tablebases,
+    tree::Tree,
+};
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Limits of a single search, as given to a UCI `go` command.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GoOptions {
+    /// Time left on the clock of each side, in milliseconds.
+    pub times: [Option<u64>; 2],
+    pub incs: [Option<u64>; 2],
+    pub movestogo: Option<u64>,
+    pub movetime: Option<u128>,
+    pub nodes: Option<usize>,
+    pub depth: Option<usize>,
+}
+
+/// Outcome of a finished search.
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    /// Move to play, in UCI notation.
+    pub best_move: String,
+    /// Principal variation in UCI notation, starting with the move
+    /// searched the most, which may differ from the move played.
+    pub pv: Vec<String>,
+    pub score: Score,
+    /// Expected outcome, combining the searched score with the
+    /// draw rate of the root evaluation.
+    pub wdl: EvalWdl,
+    pub nodes: usize,
+    /// Statistics of every root move, merged over every tree, from best to worst.
+    pub root_moves: Vec<RootMove>,
+}
+
+/// A chess engine for embedding in other programs, which searches in the
+/// background and sends its output to a callback instead of stdout.
+///
+/// Options are the same as those listed by the UCI front-end, which is
+/// itself built on top of this. Any call that needs the state of the
+/// engine waits for a running search to finish first, so should be
+/// preceded by [`Engine::stop`] when the search has no limits.
+pub struct Engine {
+    policy: &'static PolicyNetwork,
+    value: &'static ValueNetwork,
+    reporter: Arc<dyn Fn(SearchEvent) + Send + Sync>,
+    abort: Arc<AtomicBool>,
+    state: Option<Box<State>>,
+    search: Option<JoinHandle<(Box<State>, SearchResult)>>,
+    result: Option<SearchResult>,
+}
+
+impl Engine {
+    pub fn new(
+        policy: &'static PolicyNetwork,
+        value: &'static ValueNetwork,
+        reporter: impl Fn(SearchEvent) + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            policy,
+            value,
+            reporter: Arc::new(reporter),
+            abort: Arc::new(AtomicBool::new(false)),
+            state: Some(Box::default()),
+            search: None,
+            result: None,
+        }
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search
+            .as_ref()
+            .is_some_and(|search| !search.is_finished())
+    }
+
+    /// Waits for the running search, if any, and returns the
+    /// result of the last search.
+    pub fn wait(&mut self) -> Option<&SearchResult> {
+        if let Some(search) = self.search.take() {
+            let (state, result) = search.join().unwrap();
+            self.state = Some(state);
+            self.result = Some(result);
+        }
+
+        self.result.as_ref()
+    }
+
+    /// Stops the running search, if any, which then reports its best move.
+    pub fn stop(&mut self) -> Option<&SearchResult> {
+        self.abort.store(true, Ordering::Relaxed);
+        self.wait()
+    }
+
+    /// Result of the last finished search.
+    pub fn result(&self) -> Option<&SearchResult> {
+        self.result.as_ref()
+    }
+
+    fn state(&mut self) -> &mut State {
+        self.wait();
+        self.state.as_mut().unwrap()
+    }
+
+    pub fn position(&mut self) -> &ChessState {
+        &self.state().pos
+    }
+
+    pub fn params(&mut self) -> &MctsParams {
+        &self.state().params
+    }
+
+    /// Static evaluation of the current position.
+    pub fn eval(&mut self) -> EvalBreakdown {
+        let value = self.value;
+        let state = self.state();
+        state
+            .pos
+            .eval_with_contempt(value, &state.params, state.pos.stm())
+    }
+
+    /// Probability the policy network gives each legal move in the current
+    /// position, in UCI notation and from most to least likely.
+    pub fn policy(&mut self) -> Vec<(String, f32)> {
+        let policy = self.policy;
+        let pos = &self.state().pos;
+        let mut moves = Vec::new();
+
+        pos.map_moves_with_policies(policy, |mov, policy| {
+            moves.push((pos.conv_mov_to_str(mov), policy));
+        });
+
+        let max = moves
+            .iter()
+            .map(|&(_, p)| p)
+            .fold(f32::NEG_INFINITY, f32::max);
+        let mut total = 0.0;
+
+        for (_, p) in &mut moves {
+            *p = (*p - max).exp();
+            total += *p;
+        }
+
+        for (_, p) in &mut moves {
+            *p /= total;
+        }
+
+        moves.sort_by(|(_, p1), (_, p2)| p2.total_cmp(p1));
+        moves
+    }
+
+    /// Sets the position to `fen`, use [`ChessState::STARTPOS`] for the
+    /// starting position, followed by `moves` in UCI notation.
+    pub fn set_position(&mut self, fen: &str, moves: &[&str]) {
+        let state = self.state();
+
+        let mut command = format!("position fen {fen}");
+
+        if !moves.is_empty() {
+            command.push_str(" moves ");
+            command.push_str(&moves.join(" "));
+        }
+
+        state.workers.set_position(&command);
+
+        state.pos = ChessState::from_fen(fen);
+
+        for mov in moves {
+            state.pos.make_uci_move(mov);
+        }
+    }
+
+    /// Sets an option by its UCI name, or any tunable parameter by name.
+    pub fn set_option(&mut self, name: &str, value: Option<&str>) {
+        let reporter = self.reporter.clone();
+        self.state().set_option(name, value, &*reporter);
+    }
+
+    /// Forgets everything learned about the previous game.
+    pub fn new_game(&mut self) {
+        let reporter = self.reporter.clone();
+        let state = self.state();
+
+        state.save_experience(&*reporter);
+        state.root_game_ply = 0;
+        state.time_bank = 0;
+        clear_trees(&mut state.tree, &mut state.root_trees, state.threads);
+        state.workers.new_game(&*reporter);
+    }
+
+    pub fn save_experience(&mut self) {
+        let reporter = self.reporter.clone();
+        self.state().save_experience(&*reporter);
+    }
+
+    /// Starts searching the current position in the background, after
+    /// waiting for any running search to finish.
+    pub fn start_search(&mut self, options: GoOptions) {
+        self.wait();
+
+        let mut state = self.state.take().unwrap();
+        let (policy, value) = (self.policy, self.value);
+        let reporter = self.reporter.clone();
+        let abort = self.abort.clone();
+
+        abort.store(false, Ordering::Relaxed);
+
+        self.search = Some(thread::spawn(move || {
+            let result = state.go(&options, policy, value, &*reporter, &abort);
+            (state, result)
+        }));
+    }
+
+    /// Searches the current position, and waits for the result.
+    pub fn search(&mut self, options: GoOptions) -> &SearchResult {
+        self.start_search(options);
+        self.wait().unwrap()
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.stop();
+        self.save_experience();
+    }
+}
+
+/// Everything a search needs, moved to the search thread while it runs.
+struct State {
+    pos: ChessState,
+    root_game_ply: u32,
+    params: MctsParams,
+    hash_mb: usize,
+    tree: Tree,
+    root_trees: Vec<Tree>,
+    report_moves: bool,
+    threads: usize,
+    move_overhead: usize,
+    time_bank: u128,
+    uci_opponent_rating: Option<i32>,
+    uci_rating_adv: Option<i32>,
+    contempt_override: Option<i32>,
+    contempt_analysis: bool,
+    dynamic_contempt: bool,
+    contempt_both_sides: bool,
+    gumbel_root: bool,
+    deterministic: bool,
+    limit_strength: bool,
+    uci_elo: i32,
+    variety: Variety,
+    tablebases: Option<Tablebases>,
+    experience: Experience,
+    workers: Workers,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        let hash_mb = 64;
+
+        Self {
+            pos: ChessState::default(),
+            root_game_ply: 0,
+            params: MctsParams::default(),
+            hash_mb,
+            tree: Tree::new_mb(hash_mb, 1),
+            root_trees: Vec::new(),
+            report_moves: false,
+            threads: 1,
+            move_overhead: 400,
+            time_bank: 0,
+            uci_opponent_rating: None,
+            uci_rating_adv: None,
+            contempt_override: None,
+            contempt_analysis: false,
+            dynamic_contempt: false,
+            contempt_both_sides: false,
+            gumbel_root: false,
+            deterministic: false,
+            limit_strength: false,
+            uci_elo: MAX_ELO,
+            variety: Variety::default(),
+            tablebases: None,
+            experience: Experience::default(),
+            workers: Workers::default(),
+        }
+    }
+}
+
+impl State {
+    fn save_experience(&mut self, reporter: &Reporter) {
+        if let Err(err) = self.experience.save() {
+            reporter(SearchEvent::Message(format!(
+                "failed to save experience: {err}"
+            )));
+        }
+    }
+
+    fn set_option(&mut self, name: &str, value: Option<&str>, reporter: &Reporter) {
+        let message = |message: String| reporter(SearchEvent::Message(message));
+        let check = |value: &str| value.eq_ignore_ascii_case("true");
+
+        match name {
+            "report_moves" => {
+                self.report_moves = !self.report_moves;
+            }
+            "report_iters" => {
+                REPORT_ITERS.fetch_xor(true, Ordering::Relaxed);
+            }
+            "JsonOutput" => {
+                if let Some(v) = value {
+                    JSON_OUTPUT.store(check(v), Ordering::Relaxed);
+                }
+            }
+            "UCI_Chess960" => {}
+            "Contempt_Analysis" => {
+                if let Some(v) = value {
+                    self.contempt_analysis = check(v);
+                }
+            }
+            "Contempt_Analysis_Both" => {
+                if let Some(v) = value {
+                    self.contempt_both_sides = check(v);
+                }
+            }
+            "DynamicContempt" => {
+                if let Some(v) = value {
+                    self.dynamic_contempt = check(v);
+                }
+            }
+            "GumbelRoot" => {
+                if let Some(v) = value {
+                    self.gumbel_root = check(v);
+                }
+            }
+            "Deterministic" => {
+                if let Some(v) = value {
+                    self.deterministic = check(v);
+                }
+            }
+            "MoveTemperature" | "TemperatureDecayPlies" | "RootNoise" | "Seed" => {
+                if let Some(v) = value {
+                    if let Ok(parsed) = v.parse::<u32>() {
+                        let variety = &mut self.variety;
+
+                        match name {
+                            "MoveTemperature" => {
+                                variety.temperature = parsed.min(500) as f32 / 100.0
+                            }
+                            "TemperatureDecayPlies" => variety.decay_plies = parsed,
+                            "RootNoise" => variety.root_noise = parsed.min(100) as f32 / 100.0,
+                            _ => variety.seed = u64::from(parsed),
+                        }
+                    }
+                }
+            }
+            "UCI_LimitStrength" => {
+                if let Some(v) = value {
+                    self.limit_strength = check(v);
+                }
+            }
+            "UCI_Elo" => {
+                if let Some(v) = value {
+                    if let Ok(parsed) = v.parse::<i32>() {
+                        self.uci_elo = parsed.clamp(MIN_ELO, MAX_ELO);
+                    }
+                }
+            }
+            "RootParallel" => {
+                if let Some(v) = value {
+                    if let Ok(parsed) = v.parse::<usize>() {
+                        rebuild_trees(
+                            &mut self.tree,
+                            &mut self.root_trees,
+                            parsed.clamp(1, 64),
+                            self.hash_mb,
+                            self.threads,
+                        );
+                    }
+                }
+            }
+            "Workers" => {
+                self.workers.disconnect_all();
+
+                let addrs = value.filter(|&addrs| addrs != "<empty>").unwrap_or("");
+
+                for addr in addrs
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|addr| !addr.is_empty())
+                {
+                    match self.workers.connect(addr) {
+                        Ok(()) => message(format!("connected to worker {addr}")),
+                        Err(err) => message(format!("failed to connect to worker {addr}: {err}")),
+                    }
+                }
+            }
+            "TablebasePath" => {
+                self.tablebases = value
+                    .filter(|path| !path.is_empty() && *path != "<empty>")
+                    .and_then(|path| match Tablebases::load(path) {
+                        Ok(tbs) => {
+                            message(format!("loaded {} tablebases", tbs.len()));
+                            Some(tbs).filter(|tbs| !tbs.is_empty())
+                        }
+                        Err(err) => {
+                            message(format!("failed to load tablebases: {err}"));
+                            None
+                        }
+                    });
+            }
+            "Experience" | "ExperienceFile" => {
+                if let Some(v) = value {
+                    let experience = &mut self.experience;
+
+                    let result = if name == "Experience" {
+                        experience.set_enabled(check(v))
+                    } else {
+                        experience.set_path(v)
+                    };
+
+                    match result {
+                        Ok(()) if experience.enabled() => {
+                            message(format!("loaded {} experience entries", experience.len()))
+                        }
+                        Ok(()) => {}
+                        Err(err) => message(format!("failed to load experience: {err}")),
+                    }
+                }
+            }
+            "ExperienceDepth" => {
+                if let Some(v) = value {
+                    if let Ok(parsed) = v.parse() {
+                        self.experience.set_max_depth(parsed);
+                    }
+                }
+            }
+            "ExperienceMinVisits" => {
+                if let Some(v) = value {
+                    if let Ok(parsed) = v.parse() {
+                        self.experience.set_min_visits(parsed);
+                    }
+                }
+            }
+            "Threads" => {
+                if let Some(v) = value {
+                    if let Ok(parsed) = v.parse::<usize>() {
+                        self.threads = parsed.max(1);
+                        let groups = self.root_trees.len() + 1;
+                        rebuild_trees(
+                            &mut self.tree,
+                            &mut self.root_trees,
+                            groups,
+                            self.hash_mb,
+                            self.threads,
+                        );
+                    }
+                }
+            }
+            "MoveOverhead" => {
+                if let Some(v) = value {
+                    if let Ok(parsed) = v.parse::<usize>() {
+                        self.move_overhead = parsed;
+                    }
+                }
+            }
+            "Hash" => {
+                if let Some(v) = value {
+                    if let Ok(parsed) = v.parse::<i32>() {
+                        self.hash_mb = parsed.max(1) as usize;
+                        let groups = self.root_trees.len() + 1;
+                        rebuild_trees(
+                            &mut self.tree,
+                            &mut self.root_trees,
+                            groups,
+                            self.hash_mb,
+                            self.threads,
+                        );
+                    }
+                }
+            }
+            "Contempt" => {
+                if let Some(v) = value {
+                    if let Ok(parsed) = v.parse::<i32>() {
+                        let clamped = parsed.clamp(-1000, 1000);
+                        self.contempt_override = Some(clamped);
+                        self.params.set("contempt", clamped);
+                        message(format!("using contempt {clamped} elo"));
+                    }
+                }
+            }
+            "DrawScore" => {
+                if let Some(v) = value {
+                    if let Ok(parsed) = v.parse::<i32>() {
+                        self.params.set("draw_score", parsed.clamp(-100, 100));
+                    }
+                }
+            }
+            "UCI_Opponent" => {
+                if self.contempt_override.is_some() || self.uci_rating_adv.is_some() {
+                    return;
+                }
+
+                if let Some(v) = value {
+                    if let Ok(parsed) = parse_uci_opponent_rating(v) {
+                        self.uci_opponent_rating = parsed;
+                        self.apply_uci_contempt(reporter);
+                    }
+                }
+            }
+            "UCI_RatingAdv" => {
+                if self.contempt_override.is_some() {
+                    return;
+                }
+
+                if let Some(v) = value {
+                    if v.eq_ignore_ascii_case("none") {
+                        self.uci_rating_adv = None;
+                        self.apply_uci_contempt(reporter);
+                    } else if let Ok(parsed) = v.parse::<f32>() {
+                        self.uci_rating_adv = Some(parsed.round() as i32);
+                        self.apply_uci_contempt(reporter);
+                    }
+                }
+            }
+            _ => {
+                if let Some(v) = value {
+                    let parsed = v.parse::<i32>().unwrap_or(0);
+
+                    if !self.params.set(name, parsed) {
+                        message(format!("unknown option {name}"));
+                    }
+                }
+            }
+        }
+    }
+
+    fn apply_uci_contempt(&mut self, reporter: &Reporter) {
+        const DEFAULT_SELF_RATING: i32 = 3520;
+
+        let contempt = self.uci_rating_adv.or_else(|| {
+            self.uci_opponent_rating
+                .map(|opp| DEFAULT_SELF_RATING - opp)
+        });
+
+        if let Some(contempt) = contempt {
+            let clamped = contempt.clamp(-1000, 1000);
+            self.params.set("contempt", clamped);
+            reporter(SearchEvent::Message(format!(
+                "using contempt {clamped} elo"
+            )));
+        }
+    }
+
+    fn go(
+        &mut self,
+        options: &GoOptions,
+        policy: &PolicyNetwork,
+        value: &ValueNetwork,
+        reporter: &Reporter,
+        abort: &AtomicBool,
+    ) -> SearchResult {
+        // increment game ply every time `go` is called
+        self.root_game_ply += 2;
+
+        let pos = self.pos.clone();
+        let mut max_time = None;
+        let mut opt_time = None;
+
+        // `go wtime <wtime> btime <btime> winc <winc> binc <binc>``
+        if let Some(mut remaining) = options.times[pos.stm()] {
+            // apply move overhead
+            remaining = remaining.saturating_sub(self.move_overhead as u64).max(10);
+
+            let timeman = SearchHelpers::get_time(
+                remaining,
+                options.incs[pos.stm()],
+                self.root_game_ply,
+                options.movestogo,
+                &mut self.time_bank,
+                &self.params,
+            );
+
+            opt_time = Some(timeman.0);
+            max_time = Some(timeman.1);
+        }
+
+        // `go movetime <time>`
+        if let Some(max) = options.movetime {
+            // if both movetime and increment time controls given, use
+            max_time = Some(max_time.unwrap_or(u128::MAX).min(max));
+        }
+
+        if self.contempt_analysis {
+            clear_trees(&mut self.tree, &mut self.root_trees, self.threads);
+        }
+
+        set_root_position(&mut self.tree, &pos, reporter);
+
+        for root_tree in self.root_trees.iter_mut() {
+            set_root_position(root_tree, &pos, reporter);
+        }
+
+        let mut limits = Limits {
+            max_time,
+            opt_time,
+            max_depth: options.depth.unwrap_or(256),
+            max_nodes: options.nodes.unwrap_or(usize::MAX),
+            gumbel: self.gumbel_root,
+            deterministic: self.deterministic,
+            root_noise: None,
+            move_temperature: None,
+            #[cfg(feature = "datagen")]
+            kld_min_gain: None,
+        };
+
+        self.variety.apply(&mut limits, &pos);
+
+        let mut params = self.params.clone();
+
+        if self.dynamic_contempt {
+            let remaining = options.times[pos.stm()];
+            let contempt = SearchHelpers::get_dynamic_contempt(&pos, value, &params, remaining);
+            params.set("contempt", contempt);
+            reporter(SearchEvent::Message(format!(
+                "using dynamic contempt {contempt} elo"
+            )));
+        }
+
+        if self.limit_strength {
+            Strength::from_elo(self.uci_elo).apply(&mut limits, &mut params, time_seed());
+        }
+
+        // remember the final progress report for the result
+        let last_info = Mutex::new(None);
+        let reporter = |event: SearchEvent| {
+            if let SearchEvent::Info(info) = &event {
+                *last_info.lock().unwrap() = Some(info.clone());
+            }
+
+            reporter(event);
+        };
+
+        let timer = Instant::now();
+        let mut nodes = 0;
+        let tablebases = self.tablebases.as_ref();
+        let experience = Some(&self.experience).filter(|experience| experience.enabled());
+
+        let mut stopped_early = false;
+
+        let root_parallel = !self.root_trees.is_empty() || !self.workers.is_empty();
+
+        if self.contempt_both_sides && root_parallel {
+            reporter(SearchEvent::Message(
+                "Contempt_Analysis_Both needs a single search group without workers, ignoring it"
+                    .to_string(),
+            ));
+        }
+
+        let (mov, q) = if self.contempt_both_sides && !root_parallel {
+            search_both_sides(
+                &mut self.tree,
+                &pos,
+                &params,
+                policy,
+                value,
+                abort,
+                tablebases,
+                experience,
+                self.threads,
+                limits,
+                &reporter,
+            )
+        } else if root_parallel {
+            let trees = std::iter::once(&self.tree)
+                .chain(self.root_trees.iter())
+                .collect::<Vec<_>>();
+
+            let remote =
+                Some(&mut self.workers as &mut dyn RemoteSearch).filter(|w| w.searches() > 0);
+
+            search_root_parallel(
+                &trees,
+                &params,
+                policy,
+                value,
+                abort,
+                tablebases,
+                experience,
+                self.threads,
+                limits,
+                Some(&reporter),
+                &mut nodes,
+                remote,
+            )
+        } else {
+            let searcher = Searcher::new(&self.tree, &params, policy, value, abort, tablebases)
+                .with_experience(experience)
+                .with_reporter(&reporter);
+
+            let ret = searcher.search(
+                self.threads,
+                limits,
+                true,
+                &mut nodes,
+                #[cfg(feature = "datagen")]
+                false,
+                #[cfg(feature = "datagen")]
+                1.0,
+            );
+
+            if self.report_moves {
+                for line in searcher.display_moves() {
+                    reporter(SearchEvent::Message(line));
+                }
+            }
+
+            stopped_early = searcher.stopped_early();
+
+            (ret.0, ret.1)
+        };
+
+        let best_move = pos.conv_mov_to_str(mov);
+        reporter(SearchEvent::BestMove(best_move.clone()));
+
+        self.experience.record(&self.tree);
+
+        // bank the time saved on forced or settled moves for future moves
+        if let Some(opt) = opt_time.filter(|_| stopped_early) {
+            self.time_bank += opt.saturating_sub(timer.elapsed().as_millis());
+        }
+
+        let info = last_info.into_inner().unwrap();
+        let draw = pos.eval_with_contempt(value, &params, pos.stm()).raw.draw;
+
+        let root_moves = match &info {
+            Some(info) => info.root_moves.clone(),
+            None => {
+                Searcher::new(&self.tree, &params, policy, value, abort, tablebases).root_moves()
+            }
+        };
+
+        SearchResult {
+            pv: info
+                .as_ref()
+                .map_or_else(|| vec![best_move.clone()], |info| info.pv.clone()),
+            score: info.map_or(Score::from_q(q, 1), |info| info.score),
+            wdl: EvalWdl::from_draw_and_score(draw, q),
+            best_move,
+            nodes,
+            root_moves,
+        }
+    }
+}
+
+fn set_root_position(tree: &mut Tree, pos: &ChessState, reporter: &Reporter) {
+    if tree.is_empty() {
+        tree.set_root_position(pos);
+        return;
+    }
+
+    reporter(SearchEvent::Message("searching for subtree".to_string()));
+
+    let message = if tree.set_root_position(pos) {
+        "found subtree"
+    } else {
+        "no subtree found"
+    };
+
+    reporter(SearchEvent::Message(message.to_string()));
+}
+
+fn parse_uci_opponent_rating(value: &str) -> Result<Option<i32>, ()> {
+    let mut parts = value.split_whitespace();
+    // format: "none <rating|none> <type> <name>"
+    parts.next().ok_or(())?; // skip first field (unused)
+    let rating_str = parts.next().ok_or(())?;
+    if rating_str.eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+
+    rating_str.parse::<i32>().map(Some).map_err(|_| ())
+}
+
+/// Searches the position once with the contempt perspective fixed to
+/// the side not to move, then once fixed to the side to move, reporting
+/// the progress of both and their results in `info string` lines. The
+/// limits are split between the two searches, and stopping ends both, so
+/// if the first is stopped the second only has the policy of the root.
+/// The move played is the one from the second search, which is also the
+/// tree kept for reuse.
+#[allow(clippy::too_many_arguments)]
+fn search_both_sides(
+    tree: &mut Tree,
+    pos: &ChessState,
+    params: &MctsParams,
+    policy: &PolicyNetwork,
+    value: &ValueNetwork,
+    stop: &AtomicBool,
+    tablebases: Option<&Tablebases>,
+    experience: Option<&Experience>,
+    threads: usize,
+    limits: Limits,
+    reporter: &Reporter,
+) -> (Move, f32) {
+    const SIDES: [&str; 2] = ["white", "black"];
+
+    let contempt = params.contempt();
+    let mut side_limits = limits;
+    side_limits.max_time = limits.max_time.map(|time| (time / 2).max(1));
+    side_limits.opt_time = limits.opt_time.map(|time| (time / 2).max(1));
+
+    if limits.max_nodes != usize::MAX {
+        side_limits.max_nodes = (limits.max_nodes / 2).max(1);
+    }
+
+    let mut best = (Move::NULL, 0.5);
+
+    for side in [pos.stm() ^ 1, pos.stm()] {
+        // contempt is applied relative to the side to move at the root
+        let mut side_params = params.clone();
+        let perspective = if side == pos.stm() { 1 } else { -1 };
+        side_params.set("contempt", contempt * perspective);
+
+        tree.clear(threads);
+        tree.set_root_position(pos);
+
+        reporter(SearchEvent::Message(format!(
+            "searching with contempt {contempt} for {}",
+            SIDES[side]
+        )));
+
+        // a search aborts itself at its limits, so each side gets its own
+        // flag, which is set once stopped
+        let abort = AtomicBool::new(stop.load(Ordering::Relaxed));
+        let searcher = Searcher::new(tree, &side_params, policy, value, &abort, tablebases)
+            .with_experience(experience)
+            .with_reporter(reporter);
+        let timer = Instant::now();
+        let mut nodes = 0;
+
+        let (mov, score) = thread::scope(|s| {
+            let handle = s.spawn(|| {
+                let ret = searcher.search(
+                    threads,
+                    side_limits,
+                    true,
+                    &mut nodes,
+                    #[cfg(feature = "datagen")]
+                    false,
+                    #[cfg(feature = "datagen")]
+                    1.0,
+                );
+                (ret.0, ret.1)
+            });
+
+            while !handle.is_finished() {
+                if stop.load(Ordering::Relaxed) {
+                    abort.store(true, Ordering::Relaxed);
+                }
+
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            handle.join().unwrap()
+        });
+
+        reporter(SearchEvent::Message(format!(
+            "contempt {} for {}: bestmove {} score cp {:.0} nodes {nodes} time {}",
+            contempt,
+            SIDES[side],
+            pos.conv_mov_to_str(mov),
+            Searcher::get_cp(score),
+            timer.elapsed().as_millis(),
+        )));
+
+        best = (mov, score);
+    }
+
+    best
+}
+
+/// Reallocates the main tree and one extra tree for every further
+/// root-parallel group, splitting the hash and threads evenly between them.
+fn rebuild_trees(
+    tree: &mut Tree,
+    root_trees: &mut Vec<Tree>,
+    groups: usize,
+    hash_mb: usize,
+    threads: usize,
+) {
+    let group_mb = (hash_mb / groups).max(1);
+    let group_threads = threads.div_ceil(groups);
+
+    let root = tree.root_position().clone();
+    tree.rebuild(group_mb, group_threads, root);
+
+    root_trees.clear();
+    root_trees.extend((1..groups).map(|_| Tree::new_mb(group_mb, group_threads)));
+}
+
+fn clear_trees(tree: &mut Tree, root_trees: &mut [Tree], threads: usize) {
+    let group_threads = threads.div_ceil(root_trees.len() + 1);
+
+    tree.clear(group_threads);
+
+    for root_tree in root_trees {
+        root_tree.clear(group_threads);
+    }
+}
+
+/// Options for varying the moves played, for opening diversity.
+#[derive(Default)]
+struct Variety {
+    temperature: f32,
+    decay_plies: u32,
+    root_noise: f32,
+    seed: u64,
+}
+
+impl Variety {
+    const ROOT_NOISE_ALPHA: f32 = 0.3;
+
+    /// Samples the move played with a temperature that decays linearly to
+    /// zero over the first `decay_plies` plies of the game, if not zero,
+    /// and mixes noise into the root policy. A fixed seed always gives
+    /// the same samples in the same position.
+    fn apply(&self, limits: &mut Limits, pos: &ChessState) {
+        let seed = if self.seed == 0 {
+            time_seed()
+        } else {
+            self.seed ^ pos.hash()
+        };
+
+        let board = pos.board();
+        let game_ply = 2 * u32::from(board.fullm()).saturating_sub(1) + board.stm() as u32;
+
+        let decay = if self.decay_plies == 0 {
+            1.0
+        } else {
+            (1.0 - game_ply as f32 / self.decay_plies as f32).max(0.0)
+        };
+
+        let temperature = self.temperature * decay;
+
+        if temperature > 0.0 {
+            limits.move_temperature = Some(MoveTemperature { temperature, seed });
+        }
+
+        if self.root_noise > 0.0 {
+            limits.root_noise = Some(RootNoise {
+                alpha: Self::ROOT_NOISE_ALPHA,
+                epsilon: self.root_noise,
+                seed: seed.rotate_left(32),
+            });
+        }
+    }
+}
+
+fn time_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Guaranteed increasing.")
+        .as_nanos() as u64
+}
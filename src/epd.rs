@@ -0,0 +1,326 @@
+use crate::{
+    chess::ChessState,
+    engine::Engine,
+    mcts::SearchEvent,
+    networks::{PolicyNetwork, ValueNetwork},
+    uci,
+};
+
+use std::{
+    fs,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// A position of a test suite, with its `bm` (best move), `am` (avoid
+/// move) and `id` opcodes. Moves are kept as written, in SAN.
+struct Entry {
+    id: String,
+    fen: String,
+    best: Vec<String>,
+    avoid: Vec<String>,
+}
+
+/// Moves in UCI notation that solve or fail a position.
+#[derive(Default)]
+struct Solution {
+    best: Vec<String>,
+    avoid: Vec<String>,
+}
+
+impl Solution {
+    fn is_solved_by(&self, mov: &str) -> bool {
+        (self.best.is_empty() || self.best.iter().any(|m| m == mov))
+            && !self.avoid.iter().any(|m| m == mov)
+    }
+}
+
+/// Time and nodes at which the search settled on a solving move,
+/// reset whenever it switches to one that fails.
+#[derive(Default)]
+struct Tracker {
+    solution: Solution,
+    solved_at: Option<(u128, usize)>,
+}
+
+/// Runs an EPD test suite, `epd <file> [nodes <n>] [movetime <ms>]
+/// [depth <d>] [threads <n>] [hash <mb>] [csv]`, searching every position
+/// with a limit of 100000 nodes unless another limit is given.
+pub fn run(args: &[&str], policy: &'static PolicyNetwork, value: &'static ValueNetwork) {
+    let Some(&path) = args.first() else {
+        println!("usage: epd <file> [nodes <n>] [movetime <ms>] [depth <d>] [threads <n>] [hash <mb>] [csv]");
+        return;
+    };
+
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) => {
+            println!("failed to read {path}: {err}");
+            return;
+        }
+    };
+
+    // engine options and output format, leaving the search limits
+    let mut limits = Vec::new();
+    let mut engine_options = Vec::new();
+    let mut csv = false;
+    let mut rest = args[1..].iter();
+
+    while let Some(&arg) = rest.next() {
+        match arg {
+            "threads" | "hash" => {
+                let name = if arg == "threads" { "Threads" } else { "Hash" };
+                engine_options.extend(rest.next().map(|&value| (name, value)));
+            }
+            "csv" => csv = true,
+            _ => limits.push(arg),
+        }
+    }
+
+    let mut options = uci::parse_go(&limits);
+
+    if options.nodes.is_none() && options.movetime.is_none() && options.depth.is_none() {
+        options.nodes = Some(100_000);
+    }
+
+    let tracker = Arc::new(Mutex::new(Tracker::default()));
+    let reporter_tracker = tracker.clone();
+
+    let mut engine = Engine::new(policy, value, move |event| {
+        if let SearchEvent::Info(info) = event {
+            let mut tracker = reporter_tracker.lock().unwrap();
+
+            match info.pv.first() {
+                Some(mov) if tracker.solution.is_solved_by(mov) => {
+                    tracker.solved_at.get_or_insert((info.time, info.nodes));
+                }
+                _ => tracker.solved_at = None,
+            }
+        }
+    });
+
+    for (name, value) in engine_options {
+        engine.set_option(name, Some(value));
+    }
+
+    if csv {
+        println!("id,fen,bm,am,move,solved,time_ms,nodes");
+    }
+
+    let mut total = 0;
+    let mut solved = 0;
+    let timer = Instant::now();
+
+    for (line_no, line) in contents.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let Some(entry) = parse_entry(line) else {
+            eprintln!("skipping line {}: invalid position", line_no + 1);
+            continue;
+        };
+
+        let pos = ChessState::from_fen(&entry.fen);
+
+        let to_uci = |moves: &[String]| {
+            moves
+                .iter()
+                .map(|san| pos.parse_san(san).map(|mov| pos.conv_mov_to_str(mov)))
+                .collect::<Option<Vec<_>>>()
+        };
+
+        let (Some(best), Some(avoid)) = (to_uci(&entry.best), to_uci(&entry.avoid)) else {
+            eprintln!("skipping line {}: invalid move in bm or am", line_no + 1);
+            continue;
+        };
+
+        if best.is_empty() && avoid.is_empty() {
+            eprintln!("skipping line {}: no bm or am", line_no + 1);
+            continue;
+        }
+
+        *tracker.lock().unwrap() = Tracker {
+            solution: Solution { best, avoid },
+            solved_at: None,
+        };
+
+        engine.new_game();
+        engine.set_position(&entry.fen, &[]);
+
+        let start = Instant::now();
+        let result = engine.search(options).clone();
+        let elapsed = start.elapsed().as_millis();
+
+        let tracker = tracker.lock().unwrap();
+        let is_solved = tracker.solution.is_solved_by(&result.best_move);
+        let (time, nodes) = tracker.solved_at.unwrap_or((elapsed, result.nodes));
+        let found = pos
+            .parse_san(&result.best_move)
+            .map_or(result.best_move.clone(), |mov| pos.conv_mov_to_san(mov));
+
+        total += 1;
+        solved += usize::from(is_solved);
+
+        if csv {
+            let fields = [
+                entry.id.clone(),
+                entry.fen.clone(),
+                entry.best.join(" "),
+                entry.avoid.join(" "),
+                found.clone(),
+                is_solved.to_string(),
+                if is_solved {
+                    time.to_string()
+                } else {
+                    String::new()
+                },
+                if is_solved {
+                    nodes.to_string()
+                } else {
+                    String::new()
+                },
+            ];
+
+            let row = fields
+                .iter()
+                .map(|field| csv_field(field))
+                .collect::<Vec<_>>();
+            println!("{}", row.join(","));
+        } else {
+            let expected = if entry.best.is_empty() {
+                format!("am {}", entry.avoid.join(" "))
+            } else {
+                format!("bm {}", entry.best.join(" "))
+            };
+
+            let outcome = if is_solved {
+                format!("solved time {time} nodes {nodes}")
+            } else {
+                "failed".to_string()
+            };
+
+            println!(
+                "{total:>4} {:<24} {expected:<16} found {found:<7} {outcome}",
+                entry.id
+            );
+        }
+    }
+
+    let summary = format!(
+        "solved {solved}/{total} ({:.1}%) in {:.1}s",
+        100.0 * solved as f32 / total.max(1) as f32,
+        timer.elapsed().as_secs_f32()
+    );
+
+    if csv {
+        eprintln!("{summary}");
+    } else {
+        println!("{summary}");
+    }
+}
+
+/// Parses an EPD line, the first four fields of a FEN followed by
+/// opcodes ended by semicolons, also accepting a full FEN.
+fn parse_entry(line: &str) -> Option<Entry> {
+    let mut rest = line;
+    let mut fields = Vec::new();
+
+    for _ in 0..4 {
+        rest = rest.trim_start();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        fields.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+
+    let mut clocks = ["0".to_string(), "1".to_string()];
+
+    // clocks of a full FEN, before the opcodes
+    let mut tokens = rest.split_whitespace();
+
+    if let (Some(halfmove), Some(fullmove)) = (tokens.next(), tokens.next()) {
+        if halfmove.parse::<u32>().is_ok() && fullmove.trim_end_matches(';').parse::<u32>().is_ok()
+        {
+            let fullmove = fullmove.trim_end_matches(';');
+            rest = rest.trim_start()[halfmove.len()..].trim_start()[fullmove.len()..]
+                .trim_start_matches(';');
+            clocks = [halfmove.to_string(), fullmove.to_string()];
+        }
+    }
+
+    let mut entry = Entry {
+        id: String::new(),
+        fen: String::new(),
+        best: Vec::new(),
+        avoid: Vec::new(),
+    };
+
+    for operation in split_operations(rest) {
+        let Some((opcode, operands)) = operation.split_first() else {
+            continue;
+        };
+
+        match opcode.as_str() {
+            "bm" => entry.best.extend(operands.iter().cloned()),
+            "am" => entry.avoid.extend(operands.iter().cloned()),
+            "id" => entry.id = operands.join(" "),
+            "hmvc" => clocks[0] = operands.first().cloned().unwrap_or_default(),
+            "fmvn" => clocks[1] = operands.first().cloned().unwrap_or_default(),
+            _ => {}
+        }
+    }
+
+    let fen = format!("{} {} {}", fields.join(" "), clocks[0], clocks[1]);
+
+    if !ChessState::is_valid_fen(&fen) {
+        return None;
+    }
+
+    entry.fen = fen;
+    Some(entry)
+}
+
+/// Splits opcodes into operations, each a list of the opcode followed by
+/// its operands, where quoted operands may contain spaces or semicolons.
+fn split_operations(opcodes: &str) -> Vec<Vec<String>> {
+    let mut operations = Vec::new();
+    let mut operation = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+
+    for ch in opcodes.chars() {
+        match ch {
+            '"' => quoted = !quoted,
+            ';' | ' ' | '\t' if !quoted => {
+                if !token.is_empty() {
+                    operation.push(std::mem::take(&mut token));
+                }
+
+                if ch == ';' && !operation.is_empty() {
+                    operations.push(std::mem::take(&mut operation));
+                }
+            }
+            _ => token.push(ch),
+        }
+    }
+
+    if !token.is_empty() {
+        operation.push(token);
+    }
+
+    if !operation.is_empty() {
+        operations.push(operation);
+    }
+
+    operations
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
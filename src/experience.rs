@@ -0,0 +1,216 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, ErrorKind},
+};
+
+use crate::tree::Tree;
+
+/// Values of searched positions, merged into a file after each game so
+/// that later games can start from them instead of the network.
+pub struct Experience {
+    enabled: bool,
+    path: String,
+    max_depth: usize,
+    min_visits: u64,
+    entries: HashMap<u64, Entry>,
+    pending: HashMap<u64, Entry>,
+}
+
+impl Default for Experience {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: "monty.exp".to_string(),
+            max_depth: 8,
+            min_visits: 1000,
+            entries: HashMap::new(),
+            pending: HashMap::new(),
+        }
+    }
+}
+
+/// Value from the side to move's perspective.
+#[derive(Clone, Copy)]
+struct Entry {
+    q: f32,
+    visits: u32,
+}
+
+impl Entry {
+    const BYTES: usize = 16;
+
+    fn merge(&mut self, other: Entry) {
+        let visits = u64::from(self.visits) + u64::from(other.visits);
+
+        if visits > 0 {
+            let total =
+                self.q as f64 * f64::from(self.visits) + other.q as f64 * f64::from(other.visits);
+            self.q = (total / visits as f64) as f32;
+        }
+
+        self.visits = visits.min(u64::from(u32::MAX)) as u32;
+    }
+}
+
+impl Experience {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> io::Result<()> {
+        self.enabled = enabled;
+        self.reload()
+    }
+
+    pub fn set_path(&mut self, path: &str) -> io::Result<()> {
+        self.path = path.to_string();
+        self.reload()
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn set_min_visits(&mut self, min_visits: u64) {
+        self.min_visits = min_visits.max(1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn reload(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        self.pending.clear();
+
+        if self.enabled {
+            self.entries = read(&self.path)?;
+        }
+
+        Ok(())
+    }
+
+    /// Stored value of a position `depth` plies from the root, from the
+    /// side to move's perspective, if it is within `max_depth` plies.
+    pub fn probe(&self, hash: u64, depth: usize) -> Option<f32> {
+        if !self.enabled || depth > self.max_depth {
+            return None;
+        }
+
+        self.entries.get(&hash).map(|entry| entry.q)
+    }
+
+    /// Remembers the positions of the current tree that are at most
+    /// `max_depth` plies from the root and have at least `min_visits` visits.
+    pub fn record(&mut self, tree: &Tree) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut stack = vec![(tree.root_node(), tree.root_position().clone(), 0)];
+
+        while let Some((ptr, pos, depth)) = stack.pop() {
+            let node = &tree[ptr];
+
+            if node.visits() < self.min_visits {
+                continue;
+            }
+
+            // node values are from the perspective of the side that moved into them
+            let entry = Entry {
+                q: 1.0 - node.q(),
+                visits: node.visits().min(u64::from(u32::MAX)) as u32,
+            };
+
+            // with tree reuse a position is searched again on later moves,
+            // so only its most searched occurrence this game is kept
+            let pending = self.pending.entry(pos.hash()).or_insert(entry);
+            if entry.visits > pending.visits {
+                *pending = entry;
+            }
+
+            let first_child_ptr = node.actions();
+
+            if depth >= self.max_depth
+                || first_child_ptr.is_null()
+                || usize::from(first_child_ptr.half()) != tree.half()
+            {
+                continue;
+            }
+
+            for action in 0..node.num_actions() {
+                let child_ptr = first_child_ptr + action;
+                let mut child_pos = pos.clone();
+                child_pos.make_move(tree[child_ptr].parent_move());
+                stack.push((child_ptr, child_pos, depth + 1));
+            }
+        }
+    }
+
+    /// Merges the positions recorded this game into the file, which may
+    /// have been updated by other instances in the meantime.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.enabled || self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut entries = read(&self.path)?;
+
+        for (hash, entry) in self.pending.drain() {
+            entries
+                .entry(hash)
+                .and_modify(|existing| existing.merge(entry))
+                .or_insert(entry);
+        }
+
+        let mut sorted = entries.iter().collect::<Vec<_>>();
+        sorted.sort_by_key(|(&hash, _)| hash);
+
+        let mut bytes = Vec::with_capacity(sorted.len() * Entry::BYTES);
+        for (hash, entry) in sorted {
+            bytes.extend_from_slice(&hash.to_le_bytes());
+            bytes.extend_from_slice(&entry.q.to_le_bytes());
+            bytes.extend_from_slice(&entry.visits.to_le_bytes());
+        }
+
+        let temp_path = format!("{}.tmp", self.path);
+        fs::write(&temp_path, bytes)?;
+        fs::rename(&temp_path, &self.path)?;
+
+        self.entries = entries;
+
+        Ok(())
+    }
+}
+
+fn read(path: &str) -> io::Result<HashMap<u64, Entry>> {
+    let bytes = match fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(err) => return Err(err),
+    };
+
+    if bytes.len() % Entry::BYTES != 0 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{path} is not an experience file"),
+        ));
+    }
+
+    let entries = bytes
+        .chunks_exact(Entry::BYTES)
+        .map(|record| {
+            let hash = u64::from_le_bytes(record[..8].try_into().unwrap());
+            let q = f32::from_le_bytes(record[8..12].try_into().unwrap());
+            let visits = u32::from_le_bytes(record[12..].try_into().unwrap());
+            (hash, Entry { q, visits })
+        })
+        .collect();
+
+    Ok(entries)
+}
@@ -1,8 +1,18 @@
 pub mod chess;
+pub mod distributed;
+pub mod engine;
+pub mod epd;
+pub mod experience;
+pub mod json;
 pub mod mcts;
 pub mod networks;
+pub mod serve;
+pub mod tablebase;
 pub mod tree;
 pub mod uci;
+pub mod xboard;
+
+pub use engine::Engine;
 
 use memmap2::Mmap;
 
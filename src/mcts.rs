@@ -1,23 +1,33 @@
+mod gumbel;
 mod helpers;
 mod iteration;
 mod params;
+mod report;
+mod root_parallel;
 mod search_stats;
+mod strength;
 
+pub use gumbel::SequentialHalving;
 pub use helpers::SearchHelpers;
 pub use params::MctsParams;
+pub use report::{print_event, Reporter, RootMove, Score, SearchEvent, SearchInfo};
+pub use root_parallel::{search_root_parallel, RemoteSearch, RootChildStats, RootNoise};
 pub use search_stats::SearchStats;
+pub use strength::{MoveTemperature, Strength, MAX_ELO, MIN_ELO};
 
 use crate::{
-    chess::{GameState, Move},
+    chess::{EvalWdl, GameState, Move},
+    experience::Experience,
     networks::{PolicyNetwork, ValueNetwork},
-    tree::{NodePtr, Tree},
+    tablebase::Tablebases,
+    tree::{Node, NodePtr, Tree},
 };
 
-#[cfg(feature = "datagen")]
-use crate::tree::Node;
-
 use std::{
-    sync::atomic::{AtomicBool, Ordering},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Barrier, Mutex,
+    },
     thread,
     time::Instant,
 };
@@ -30,12 +40,19 @@ pub type SearchRet = (Move, f32);
 
 pub static REPORT_ITERS: AtomicBool = AtomicBool::new(false);
 
+/// Whether [`print_event`] prints events as JSON rather than UCI lines.
+pub static JSON_OUTPUT: AtomicBool = AtomicBool::new(false);
+
 #[derive(Clone, Copy)]
 pub struct Limits {
     pub max_time: Option<u128>,
     pub opt_time: Option<u128>,
     pub max_depth: usize,
     pub max_nodes: usize,
+    pub gumbel: bool,
+    pub deterministic: bool,
+    pub root_noise: Option<RootNoise>,
+    pub move_temperature: Option<MoveTemperature>,
     #[cfg(feature = "datagen")]
     pub kld_min_gain: Option<f64>,
 }
@@ -46,6 +63,11 @@ pub struct Searcher<'a> {
     policy: &'a PolicyNetwork,
     value: &'a ValueNetwork,
     abort: &'a AtomicBool,
+    tablebases: Option<&'a Tablebases>,
+    experience: Option<&'a Experience>,
+    halving: Mutex<Option<SequentialHalving>>,
+    stopped_early: AtomicBool,
+    reporter: &'a Reporter<'a>,
 }
 
 impl<'a> Searcher<'a> {
@@ -55,6 +77,7 @@ impl<'a> Searcher<'a> {
         policy: &'a PolicyNetwork,
         value: &'a ValueNetwork,
         abort: &'a AtomicBool,
+        tablebases: Option<&'a Tablebases>,
     ) -> Self {
         Self {
             tree,
@@ -62,9 +85,38 @@ impl<'a> Searcher<'a> {
             policy,
             value,
             abort,
+            tablebases,
+            experience: None,
+            halving: Mutex::new(None),
+            stopped_early: AtomicBool::new(false),
+            reporter: &print_event,
         }
     }
 
+    /// Sends the output of searches to `reporter` instead of stdout.
+    pub fn with_reporter(mut self, reporter: &'a Reporter<'a>) -> Self {
+        self.reporter = reporter;
+        self
+    }
+
+    /// Uses the values stored in `experience` near the root
+    /// in place of the network.
+    pub fn with_experience(mut self, experience: Option<&'a Experience>) -> Self {
+        self.experience = experience;
+        self
+    }
+
+    /// Whether the last search stopped before its time was up, because
+    /// the move was forced or the best move could no longer change.
+    pub fn stopped_early(&self) -> bool {
+        self.stopped_early.load(Ordering::Relaxed)
+    }
+
+    fn stop_early(&self) -> bool {
+        self.stopped_early.store(true, Ordering::Relaxed);
+        true
+    }
+
     #[allow(clippy::too_many_arguments)]
     fn playout_until_full_main(
         &self,
@@ -75,11 +127,11 @@ impl<'a> Searcher<'a> {
         best_move: &mut Move,
         best_move_changes: &mut i32,
         previous_score: &mut f32,
-        #[cfg(feature = "datagen")] previous_kld: &mut Vec<i32>,
+        previous_kld: &mut Vec<i32>,
         #[cfg(not(feature = "uci-minimal"))] uci_output: bool,
-        thread_id: usize,
+        threads: usize,
     ) {
-        if self.playout_until_full_internal(search_stats, true, thread_id, || {
+        let stop = || {
             self.check_limits(
                 limits,
                 timer,
@@ -89,16 +141,118 @@ impl<'a> Searcher<'a> {
                 best_move,
                 best_move_changes,
                 previous_score,
-                #[cfg(feature = "datagen")]
                 previous_kld,
                 #[cfg(not(feature = "uci-minimal"))]
                 uci_output,
             )
-        }) {
+        };
+
+        let stopped = if limits.deterministic {
+            self.playout_until_full_lockstep(search_stats, threads, stop)
+        } else {
+            self.playout_until_full_internal(search_stats, true, 0, stop)
+        };
+
+        if stopped {
             self.abort.store(true, Ordering::Relaxed);
         }
     }
 
+    /// Runs iterations in lockstep batches of one per thread. Leaves are
+    /// selected and backpropagated in thread order by the calling thread,
+    /// and only evaluated in parallel, so for a given thread count the
+    /// resulting tree does not depend on thread timings.
+    fn playout_until_full_lockstep<F>(
+        &self,
+        search_stats: &SearchStats,
+        threads: usize,
+        mut stop: F,
+    ) -> bool
+    where
+        F: FnMut() -> bool,
+    {
+        let pending: Vec<Mutex<Option<iteration::PendingIteration>>> =
+            (0..threads).map(|_| Mutex::new(None)).collect();
+        let barrier = Barrier::new(threads);
+        let done = AtomicBool::new(false);
+
+        let evaluate = |thread_id: usize| {
+            if let Some(pending) = pending[thread_id].lock().unwrap().as_mut() {
+                iteration::evaluate(self, pending);
+            }
+        };
+
+        thread::scope(|s| {
+            for i in 1..threads {
+                let (barrier, done, evaluate) = (&barrier, &done, &evaluate);
+
+                s.spawn(move || loop {
+                    barrier.wait();
+
+                    if done.load(Ordering::Relaxed) {
+                        break;
+                    }
+
+                    evaluate(i);
+                    barrier.wait();
+                });
+            }
+
+            let stopped = loop {
+                let mut full = false;
+
+                for (thread_id, slot) in pending.iter().enumerate() {
+                    let root_action = if thread_id == 0 {
+                        self.next_root_action()
+                    } else {
+                        None
+                    };
+
+                    let selected = iteration::select(self, thread_id, root_action);
+                    full |= selected.is_none();
+                    *slot.lock().unwrap() = selected;
+                }
+
+                barrier.wait();
+                evaluate(0);
+                barrier.wait();
+
+                for (thread_id, slot) in pending.iter().enumerate() {
+                    if let Some(pending) = slot.lock().unwrap().take() {
+                        let depth = iteration::backpropagate(self, pending, thread_id);
+                        search_stats.add_iter(thread_id, depth, thread_id == 0);
+                    }
+                }
+
+                if full {
+                    break false;
+                }
+
+                // proven checkmate, stop signal sent or limits reached
+                if self.tree[self.tree.root_node()].is_terminal()
+                    || self.abort.load(Ordering::Relaxed)
+                    || stop()
+                {
+                    break true;
+                }
+            };
+
+            done.store(true, Ordering::Relaxed);
+            barrier.wait();
+
+            stopped
+        })
+    }
+
+    /// Root child to search next under sequential halving, if enabled.
+    fn next_root_action(&self) -> Option<usize> {
+        self.halving
+            .lock()
+            .unwrap()
+            .as_mut()
+            .map(|halving| halving.next_action(self))
+    }
+
     fn playout_until_full_worker(&self, search_stats: &SearchStats, thread_id: usize) {
         let _ = self.playout_until_full_internal(search_stats, false, thread_id, || false);
     }
@@ -114,20 +268,15 @@ impl<'a> Searcher<'a> {
         F: FnMut() -> bool,
     {
         loop {
-            let mut pos = self.tree.root_position().clone();
-            let mut this_depth = 0;
+            let root_action = if main_thread {
+                self.next_root_action()
+            } else {
+                None
+            };
 
-            if iteration::perform_one(
-                self,
-                &mut pos,
-                self.tree.root_node(),
-                &mut this_depth,
-                thread_id,
-            )
-            .is_none()
-            {
+            let Some(this_depth) = iteration::perform_one(self, thread_id, root_action) else {
                 return false;
-            }
+            };
 
             search_stats.add_iter(thread_id, this_depth, main_thread);
 
@@ -157,7 +306,7 @@ impl<'a> Searcher<'a> {
         best_move: &mut Move,
         best_move_changes: &mut i32,
         previous_score: &mut f32,
-        #[cfg(feature = "datagen")] previous_kld_state: &mut Vec<i32>,
+        previous_kld_state: &mut Vec<i32>,
         #[cfg(not(feature = "uci-minimal"))] uci_output: bool,
     ) -> bool {
         let iters = search_stats.main_iters();
@@ -169,18 +318,7 @@ impl<'a> Searcher<'a> {
         #[cfg(feature = "datagen")]
         {
             if let Some(min_gain) = limits.kld_min_gain {
-                let node = &self.tree[self.tree.root_node()];
-                let child_ptr = node.actions();
-
-                // Force i32 element type
-                let mut visit_dist: Vec<i32> = vec![0; node.num_actions()];
-
-                for (action, visits) in visit_dist.iter_mut().enumerate() {
-                    let v = self.tree[child_ptr + action].visits();
-                    // Saturate to i32::MAX (works whether visits() is u64, or usize)
-                    let v_i32 = (v as i64).min(i32::MAX as i64) as i32;
-                    *visits = v_i32;
-                }
+                let visit_dist = self.root_visit_distribution();
 
                 if let Some(kld_gain) = Node::kld_gain(&visit_dist, previous_kld_state) {
                     if kld_gain < min_gain {
@@ -191,6 +329,17 @@ impl<'a> Searcher<'a> {
             }
         }
 
+        // smart pruning on the node budget
+        #[cfg(not(feature = "datagen"))]
+        if iters.is_multiple_of(1024) && limits.max_nodes != usize::MAX && !limits.gumbel {
+            let total_iters = search_stats.total_iters();
+            let remaining = limits.max_nodes.saturating_sub(total_iters);
+
+            if SearchHelpers::smart_prune(self, total_iters, remaining as f32) {
+                return self.stop_early();
+            }
+        }
+
         if iters.is_multiple_of(128) {
             if let Some(time) = limits.max_time {
                 if timer.elapsed().as_millis() >= time {
@@ -198,7 +347,7 @@ impl<'a> Searcher<'a> {
                 }
             }
 
-            let (_, new_best_move, _) = self.get_best_action(self.tree.root_node());
+            let (_, new_best_move, _) = self.get_best_action(self.tree.root_node(), 0);
             if new_best_move != *best_move {
                 *best_move = new_best_move;
                 *best_move_changes += 1;
@@ -207,15 +356,20 @@ impl<'a> Searcher<'a> {
 
         if iters.is_multiple_of(4096) {
             if let Some(time) = limits.opt_time {
-                let (should_stop, score) = SearchHelpers::soft_time_cutoff(
+                let (should_stop, early, score) = SearchHelpers::soft_time_cutoff(
                     self,
                     timer,
                     *previous_score,
                     *best_move_changes,
                     iters,
+                    search_stats.total_iters(),
                     time,
                 );
 
+                if early {
+                    return self.stop_early();
+                }
+
                 if should_stop {
                     return true;
                 }
@@ -230,6 +384,16 @@ impl<'a> Searcher<'a> {
                     (score + 2.0 * *previous_score) / 3.0
                 };
             }
+
+            // smart pruning once the root visit distribution has settled
+            #[cfg(not(feature = "datagen"))]
+            if (limits.opt_time.is_some() || limits.max_nodes != usize::MAX) && !limits.gumbel {
+                let total_iters = search_stats.total_iters();
+
+                if self.root_kld_converged(total_iters, previous_kld_state) {
+                    return self.stop_early();
+                }
+            }
         }
 
         // define "depth" as the average depth of selection
@@ -249,6 +413,7 @@ impl<'a> Searcher<'a> {
                     timer,
                     search_stats.total_nodes(),
                     search_stats.total_iters(),
+                    false,
                 );
 
                 *timer_last_output = Instant::now();
@@ -263,6 +428,7 @@ impl<'a> Searcher<'a> {
                 timer,
                 search_stats.total_nodes(),
                 search_stats.total_iters(),
+                false,
             );
 
             *timer_last_output = Instant::now();
@@ -299,7 +465,10 @@ impl<'a> Searcher<'a> {
             self.tree
                 .expand_node(ptr, pos, self.params, self.policy, 1, 0);
 
-            let root_eval = pos.get_value_wdl(self.value, self.params, root_stm);
+            let root_eval = self
+                .experience
+                .and_then(|experience| experience.probe(pos.hash(), 0))
+                .unwrap_or_else(|| pos.get_value_wdl(self.value, self.params, root_stm));
             self.tree.update_node_stats(ptr, 1.0 - root_eval, 0);
         }
         // relabel preexisting root policies with root PST value
@@ -326,19 +495,48 @@ impl<'a> Searcher<'a> {
         // add dirichlet noise in datagen
         #[cfg(feature = "datagen")]
         if use_dirichlet_noise {
-            let alpha = 0.03;
-            let epsilon: f32 = if cfg!(feature = "policy") { 0.05 } else { 0.25 };
+            self.add_root_noise(RootNoise {
+                alpha: 0.03,
+                epsilon: if cfg!(feature = "policy") { 0.05 } else { 0.25 },
+                seed: rand::random(),
+            });
+        }
+
+        if let Some(noise) = limits.root_noise {
+            self.add_root_noise(noise);
+        }
+
+        // spend as little time as possible when the move is forced
+        self.stopped_early.store(false, Ordering::Relaxed);
+        let unforced = limits;
+        let limits = SearchHelpers::forced_limits(self, limits);
 
-            self.tree.add_dirichlet_noise_to_node(node, alpha, epsilon);
+        if limits.opt_time != unforced.opt_time || limits.max_nodes != unforced.max_nodes {
+            self.stop_early();
         }
 
+        // sequential halving needs a fixed budget, and is
+        // inherently sequential so only runs on one thread
+        let use_gumbel =
+            limits.gumbel && limits.max_nodes != usize::MAX && self.tree[node].num_actions() > 1;
+
+        let threads = if use_gumbel {
+            *self.halving.lock().unwrap() = Some(SequentialHalving::new(
+                self,
+                limits.max_nodes,
+                limits.deterministic,
+            ));
+            1
+        } else {
+            threads
+        };
+
         let search_stats = SearchStats::new(threads);
         let stats_ref = &search_stats;
 
         let mut best_move = Move::NULL;
         let mut best_move_changes = 0;
         let mut previous_score = f32::NEG_INFINITY;
-        #[cfg(feature = "datagen")]
         let mut previous_kld = Vec::new();
 
         // search loop
@@ -354,14 +552,18 @@ impl<'a> Searcher<'a> {
                         &mut best_move,
                         &mut best_move_changes,
                         &mut previous_score,
-                        #[cfg(feature = "datagen")]
                         &mut previous_kld,
                         #[cfg(not(feature = "uci-minimal"))]
                         uci_output,
-                        0,
+                        threads,
                     );
                 });
 
+                // lockstep search manages its own threads
+                if limits.deterministic {
+                    return;
+                }
+
                 for i in 1..threads {
                     s.spawn(move || self.playout_until_full_worker(stats_ref, i));
                 }
@@ -383,24 +585,105 @@ impl<'a> Searcher<'a> {
                 &timer,
                 search_stats.total_nodes(),
                 search_stats.total_iters(),
+                true,
             );
+
+            if limits.deterministic {
+                (self.reporter)(SearchEvent::Message(format!(
+                    "tree checksum {:016x}",
+                    self.tree.checksum()
+                )));
+            }
         }
 
-        let (_, _mov, q) = self.get_best_action(self.tree.root_node());
+        let (_, _mov, mut q) = self.get_best_action(self.tree.root_node(), 0);
+
+        let gumbel_mov = self.halving.lock().unwrap().take().map(|halving| {
+            let root = self.tree.root_node();
+            let child = &self.tree[self.tree[root].actions() + halving.best_action(self)];
+            q = child.q();
+            child.parent_move()
+        });
 
         #[cfg(not(feature = "datagen"))]
         {
-            let selected_mov = _mov;
+            let selected_mov = gumbel_mov.unwrap_or_else(|| match limits.move_temperature {
+                Some(temp) => self.tree.get_best_child_temp(
+                    self.tree.root_node(),
+                    temp.temperature,
+                    temp.sample(),
+                ),
+                None => _mov,
+            });
             (selected_mov, q)
         }
 
         #[cfg(feature = "datagen")]
         {
-            let selected_mov = self.tree.get_best_child_temp(self.tree.root_node(), temp);
+            use rand::prelude::*;
+
+            let selected_mov = gumbel_mov.unwrap_or_else(|| {
+                let (temp, sample) = match limits.move_temperature {
+                    Some(move_temp) => (move_temp.temperature, move_temp.sample()),
+                    None => (temp, rand::rng().random::<f64>()),
+                };
+
+                self.tree
+                    .get_best_child_temp(self.tree.root_node(), temp, sample)
+            });
             (selected_mov, q, search_stats.total_iters())
         }
     }
 
+    fn root_visit_distribution(&self) -> Vec<i32> {
+        let node = &self.tree[self.tree.root_node()];
+        let child_ptr = node.actions();
+
+        // Force i32 element type
+        let mut visit_dist: Vec<i32> = vec![0; node.num_actions()];
+
+        for (action, visits) in visit_dist.iter_mut().enumerate() {
+            let v = self.tree[child_ptr + action].visits();
+            // Saturate to i32::MAX (works whether visits() is u64, or usize)
+            let v_i32 = (v as i64).min(i32::MAX as i64) as i32;
+            *visits = v_i32;
+        }
+
+        visit_dist
+    }
+
+    #[cfg(not(feature = "datagen"))]
+    fn root_kld_converged(&self, iters: usize, previous_kld_state: &mut Vec<i32>) -> bool {
+        let threshold = f64::from(self.params.smart_prune_kld()) / 1_000_000.0;
+
+        if threshold <= 0.0 {
+            return false;
+        }
+
+        let visit_dist = self.root_visit_distribution();
+
+        // only compare over moves that were already visited, as
+        // `Node::kld_gain` is undefined for unvisited moves
+        let (new, old): (Vec<i32>, Vec<i32>) = visit_dist
+            .iter()
+            .zip(previous_kld_state.iter())
+            .filter(|(_, &old)| old > 0)
+            .map(|(&new, &old)| (new, old))
+            .unzip();
+
+        let converged = iters >= self.params.smart_prune_min_iters() as usize
+            && visit_dist.len() == previous_kld_state.len()
+            && new.iter().sum::<i32>() > old.iter().sum::<i32>()
+            && Node::kld_gain(&new, &old).is_some_and(|gain| gain < threshold);
+
+        *previous_kld_state = visit_dist;
+
+        converged
+    }
+
+    /// Reports the progress of the search, with the root moves and the
+    /// expected outcome only if `last` or with JSON output, as finding
+    /// them walks the line after every root move.
     fn search_report(
         &self,
         depth: usize,
@@ -408,18 +691,10 @@ impl<'a> Searcher<'a> {
         timer: &Instant,
         nodes: usize,
         iters: usize,
+        last: bool,
     ) {
-        print!("info depth {depth} seldepth {seldepth} ");
         let (pv_line, score) = self.get_pv(depth);
-
-        if score > 1.0 {
-            print!("score mate {} ", pv_line.len().div_ceil(2));
-        } else if score < 0.0 {
-            print!("score mate -{} ", pv_line.len() / 2);
-        } else {
-            let cp = Searcher::get_cp(score);
-            print!("score cp {cp:.0} ");
-        }
+        let detailed = last || JSON_OUTPUT.load(Ordering::Relaxed);
 
         let nodes = if REPORT_ITERS.load(Ordering::Relaxed) {
             iters
@@ -430,27 +705,36 @@ impl<'a> Searcher<'a> {
         let nps = nodes as f32 / elapsed.as_secs_f32();
         let ms = elapsed.as_millis();
 
-        print!("time {ms} nodes {nodes} nps {nps:.0} pv");
-
-        for mov in pv_line {
-            print!(" {}", self.tree.root_position().conv_mov_to_str(mov));
-        }
+        let info = SearchInfo {
+            depth: Some((depth, seldepth)),
+            score: Score::from_q(score, pv_line.len()),
+            wdl: detailed.then(|| self.root_wdl(score)),
+            time: ms,
+            nodes,
+            iters,
+            nps,
+            hashfull: self.tree.hashfull(),
+            pv: pv_line
+                .into_iter()
+                .map(|mov| self.tree.root_position().conv_mov_to_str(mov))
+                .collect(),
+            root_moves: if detailed {
+                self.root_moves()
+            } else {
+                Vec::new()
+            },
+        };
 
-        println!();
+        (self.reporter)(SearchEvent::Info(info));
     }
 
     fn get_pv(&self, mut depth: usize) -> (Vec<Move>, f32) {
         let mate = self.tree[self.tree.root_node()].is_terminal();
 
-        let (mut ptr, mut mov, q) = self.get_best_action(self.tree.root_node());
+        let (mut ptr, mut mov, q) = self.get_best_action(self.tree.root_node(), 0);
 
         let score = if !ptr.is_null() {
-            match self.tree[ptr].state() {
-                GameState::Lost(_) => 1.1,
-                GameState::Won(_) => -0.1,
-                GameState::Draw => 0.5,
-                GameState::Ongoing => q,
-            }
+            Self::child_score(self.tree[ptr].state(), q)
         } else {
             q
         };
@@ -460,42 +744,130 @@ impl<'a> Searcher<'a> {
 
         while (mate || depth > 0) && !ptr.is_null() && ptr.half() == half {
             pv.push(mov);
-            let idx = self.get_best_child(ptr);
+            let idx = self.get_best_child(ptr, pv.len());
 
             if idx == usize::MAX {
                 break;
             }
 
-            (ptr, mov, _) = self.get_best_action(ptr);
+            (ptr, mov, _) = self.get_best_action(ptr, pv.len());
             depth = depth.saturating_sub(1);
         }
 
         (pv, score)
     }
 
-    fn get_best_action(&self, node: NodePtr) -> (NodePtr, Move, f32) {
-        let idx = self.get_best_child(node);
+    /// Statistics of every root move, from best to worst, each with the
+    /// line expected to follow it.
+    pub fn root_moves(&self) -> Vec<RootMove> {
+        let root = self.tree.root_node();
+        let pos = self.tree.root_position();
+        let half = self.tree.half() > 0;
+        let first_child_ptr = self.tree[root].actions();
+        let draw = self.draw_utility(0);
+
+        let mut moves = (0..self.tree[root].num_actions())
+            .map(|action| {
+                let mut ptr = first_child_ptr + action;
+                let child = &self.tree[ptr];
+                let mut pv = vec![child.parent_move()];
+
+                while ptr.half() == half {
+                    if self.get_best_child(ptr, pv.len()) == usize::MAX {
+                        break;
+                    }
+
+                    let mov;
+                    (ptr, mov, _) = self.get_best_action(ptr, pv.len());
+
+                    if ptr.is_null() {
+                        break;
+                    }
+
+                    pv.push(mov);
+                }
+
+                let q = Self::child_score(child.state(), child.q());
+                let key = Self::best_child_key(child.visits(), child.state(), child.q(), draw);
+
+                let root_move = RootMove {
+                    mov: pos.conv_mov_to_str(child.parent_move()),
+                    visits: child.visits(),
+                    score: Score::from_q(q, pv.len()),
+                    q: child.q(),
+                    policy: child.policy(),
+                    state: child.state(),
+                    pv: pv.into_iter().map(|mov| pos.conv_mov_to_str(mov)).collect(),
+                };
+
+                (key, root_move)
+            })
+            .collect::<Vec<_>>();
+
+        moves.sort_by(|(k1, _), (k2, _)| k2.total_cmp(k1));
+        moves.into_iter().map(|(_, root_move)| root_move).collect()
+    }
+
+    /// Expected outcome of a root `score`, taking the draw
+    /// rate from the evaluation of the root position.
+    fn root_wdl(&self, score: f32) -> EvalWdl {
+        let pos = self.tree.root_position();
+        let draw = pos
+            .eval_with_contempt(self.value, self.params, pos.stm())
+            .raw
+            .draw;
+
+        EvalWdl::from_draw_and_score(draw, score)
+    }
+
+    /// Score of a child, with proven results pushed out of `[0, 1]`.
+    fn child_score(state: GameState, q: f32) -> f32 {
+        match state {
+            GameState::Lost(_) => 1.1,
+            GameState::Won(_) => -0.1,
+            GameState::Draw => 0.5,
+            GameState::Ongoing => q,
+        }
+    }
+
+    /// Best child of `node`, which is `ply` plies below the root.
+    fn get_best_action(&self, node: NodePtr, ply: usize) -> (NodePtr, Move, f32) {
+        let idx = self.get_best_child(node, ply);
         let ptr = self.tree[node].actions() + idx;
         let child = &self.tree[ptr];
         (ptr, child.parent_move(), child.q())
     }
 
-    fn get_best_child(&self, node: NodePtr) -> usize {
+    fn get_best_child(&self, node: NodePtr, ply: usize) -> usize {
+        let draw = self.draw_utility(ply);
+
         self.tree.get_best_child_by_key(node, |child| {
-            if child.visits() == 0 {
-                f32::NEG_INFINITY
-            } else {
-                match child.state() {
-                    GameState::Lost(n) => 1.0 + f32::from(n),
-                    GameState::Won(n) => f32::from(n) - 256.0,
-                    GameState::Draw => 0.5,
-                    GameState::Ongoing => child.q(),
-                }
-            }
+            Self::best_child_key(child.visits(), child.state(), child.q(), draw)
         })
     }
 
-    fn get_cp(score: f32) -> f32 {
+    /// Utility of a draw for the side to move `ply` plies below the root.
+    fn draw_utility(&self, ply: usize) -> f32 {
+        let root_stm = self.tree.root_position().stm();
+        self.params.draw_utility(root_stm ^ (ply & 1), root_stm)
+    }
+
+    /// Orders proven wins first, then unproven moves and proven draws
+    /// by value, then proven losses, and unvisited moves last.
+    fn best_child_key(visits: u64, state: GameState, q: f32, draw: f32) -> f32 {
+        if visits == 0 {
+            f32::NEG_INFINITY
+        } else {
+            match state {
+                GameState::Lost(n) => 1.0 + f32::from(n),
+                GameState::Won(n) => f32::from(n) - 256.0,
+                GameState::Draw => draw,
+                GameState::Ongoing => q,
+            }
+        }
+    }
+
+    pub fn get_cp(score: f32) -> f32 {
         // Exact mathematical clamp points (f64 for precision)
         const S_MIN: f64 = 0.329002405333_f64;
         const S_MAX: f64 = 0.670997594667_f64;
@@ -506,20 +878,25 @@ impl<'a> Searcher<'a> {
         cp.clamp(-5000.0, 5000.0) as f32
     }
 
-    pub fn display_moves(&self) {
+    /// One line for every root move, with its value, visits and state.
+    pub fn display_moves(&self) -> Vec<String> {
         let first_child_ptr = self.tree[self.tree.root_node()].actions();
-        for action in 0..self.tree[self.tree.root_node()].num_actions() {
-            let child = &self.tree[first_child_ptr + action];
-            let mov = self
-                .tree
-                .root_position()
-                .conv_mov_to_str(child.parent_move());
-            let q = child.q() * 100.0;
-            println!(
-                "{mov} -> {q:.2}% V({}) S({})",
-                child.visits(),
-                child.state()
-            );
-        }
+
+        (0..self.tree[self.tree.root_node()].num_actions())
+            .map(|action| {
+                let child = &self.tree[first_child_ptr + action];
+                let mov = self
+                    .tree
+                    .root_position()
+                    .conv_mov_to_str(child.parent_move());
+                let q = child.q() * 100.0;
+
+                format!(
+                    "{mov} -> {q:.2}% V({}) S({})",
+                    child.visits(),
+                    child.state()
+                )
+            })
+            .collect()
     }
 }
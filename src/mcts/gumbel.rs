@@ -0,0 +1,248 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    mcts::{MctsParams, Searcher},
+    tree::Tree,
+};
+
+/// Gumbel-top-k sampling of root candidates followed by
+/// sequential halving of the iteration budget between them.
+///
+/// See "Policy improvement by planning with Gumbel" (Danihelka et al.).
+pub struct SequentialHalving {
+    scores: Vec<f32>,
+    candidates: Vec<usize>,
+    budget: usize,
+    phases_left: usize,
+    visits_per_candidate: usize,
+    rounds: usize,
+    cursor: usize,
+}
+
+impl SequentialHalving {
+    pub fn new(searcher: &Searcher, budget: usize, deterministic: bool) -> Self {
+        let tree = searcher.tree;
+        let node = &tree[tree.root_node()];
+        let first_child_ptr = node.actions();
+
+        let mut rng = if deterministic {
+            Rand(0x9E37_79B9_7F4A_7C15)
+        } else {
+            Rand::with_seed()
+        };
+
+        let scores = (0..node.num_actions())
+            .map(|action| policy_logit(tree[first_child_ptr + action].policy()) + rng.gumbel())
+            .collect::<Vec<_>>();
+
+        let mut candidates = (0..node.num_actions()).collect::<Vec<_>>();
+        candidates.sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+        candidates.truncate(searcher.params.gumbel_actions().max(1) as usize);
+
+        let phases_left = candidates.len().next_power_of_two().trailing_zeros().max(1) as usize;
+
+        let mut halving = Self {
+            scores,
+            candidates,
+            budget,
+            phases_left,
+            visits_per_candidate: 0,
+            rounds: 0,
+            cursor: 0,
+        };
+
+        halving.visits_per_candidate = halving.phase_visits();
+
+        halving
+    }
+
+    fn phase_visits(&self) -> usize {
+        (self.budget / (self.phases_left * self.candidates.len())).max(1)
+    }
+
+    /// The root action the next iteration must take.
+    pub fn next_action(&mut self, searcher: &Searcher) -> usize {
+        if self.rounds >= self.visits_per_candidate && self.candidates.len() > 1 {
+            self.halve(searcher);
+        }
+
+        let action = self.candidates[self.cursor];
+
+        self.cursor += 1;
+        if self.cursor == self.candidates.len() {
+            self.cursor = 0;
+            self.rounds += 1;
+        }
+
+        action
+    }
+
+    fn halve(&mut self, searcher: &Searcher) {
+        let scores = self.completed_scores(searcher.tree, searcher.params);
+
+        self.candidates
+            .sort_by(|&a, &b| scores[b].total_cmp(&scores[a]));
+        self.candidates.truncate(self.candidates.len().div_ceil(2));
+
+        self.phases_left = self.phases_left.saturating_sub(1).max(1);
+        self.visits_per_candidate = self.phase_visits();
+        self.rounds = 0;
+        self.cursor = 0;
+    }
+
+    fn completed_scores(&self, tree: &Tree, params: &MctsParams) -> Vec<f32> {
+        let sigma = sigma_q(tree, params);
+
+        self.scores
+            .iter()
+            .zip(sigma.iter())
+            .map(|(g, s)| g + s)
+            .collect()
+    }
+
+    /// The remaining candidate with the best Gumbel score.
+    pub fn best_action(&self, searcher: &Searcher) -> usize {
+        let scores = self.completed_scores(searcher.tree, searcher.params);
+
+        *self
+            .candidates
+            .iter()
+            .max_by(|&&a, &&b| scores[a].total_cmp(&scores[b]))
+            .unwrap()
+    }
+
+    /// Improved policy target `softmax(logits + sigma(completed Q))`
+    /// over all root actions.
+    pub fn improved_policy(tree: &Tree, params: &MctsParams) -> Vec<f32> {
+        let node = &tree[tree.root_node()];
+        let first_child_ptr = node.actions();
+
+        let sigma = sigma_q(tree, params);
+
+        let mut policy = (0..node.num_actions())
+            .map(|action| policy_logit(tree[first_child_ptr + action].policy()) + sigma[action])
+            .collect::<Vec<_>>();
+
+        let max = policy.iter().fold(f32::NEG_INFINITY, |a, &b| a.max(b));
+        let mut total = 0.0;
+
+        for p in &mut policy {
+            *p = (*p - max).exp();
+            total += *p;
+        }
+
+        for p in &mut policy {
+            *p /= total;
+        }
+
+        policy
+    }
+}
+
+fn policy_logit(policy: f32) -> f32 {
+    policy.max(1e-6).ln()
+}
+
+/// Monotonic transform of the completed Q values of each root action,
+/// where unvisited actions are completed with the mixed value estimate.
+fn sigma_q(tree: &Tree, params: &MctsParams) -> Vec<f32> {
+    let root = &tree[tree.root_node()];
+    let first_child_ptr = root.actions();
+    let children = (0..root.num_actions())
+        .map(|action| &tree[first_child_ptr + action])
+        .collect::<Vec<_>>();
+
+    let max_visits = children.iter().map(|c| c.visits()).max().unwrap_or(0);
+    let total_visits = children.iter().map(|c| c.visits()).sum::<u64>() as f32;
+
+    let mut visited_policy = 0.0;
+    let mut weighted_q = 0.0;
+    for child in children.iter().filter(|c| c.visits() > 0) {
+        visited_policy += child.policy();
+        weighted_q += child.policy() * child.q();
+    }
+
+    // root value from the side to move's perspective
+    let value = 1.0 - root.q();
+    let v_mix = if visited_policy > 0.0 {
+        (value + total_visits * weighted_q / visited_policy) / (1.0 + total_visits)
+    } else {
+        value
+    };
+
+    let scale = (params.gumbel_c_visit() + max_visits as f32) * params.gumbel_c_scale();
+
+    children
+        .iter()
+        .map(|child| {
+            let q = if child.visits() > 0 { child.q() } else { v_mix };
+            scale * q
+        })
+        .collect()
+}
+
+pub(super) struct Rand(u64);
+
+impl Rand {
+    /// Scrambles `seed` so that nearby seeds give unrelated sequences.
+    pub(super) fn new(seed: u64) -> Self {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+
+        Self((z ^ (z >> 31)) | 1)
+    }
+
+    pub(super) fn with_seed() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Guaranteed increasing.")
+            .as_nanos() as u64;
+
+        Self(seed | 1)
+    }
+
+    fn rand(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    pub(super) fn uniform(&mut self) -> f64 {
+        ((self.rand() >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    }
+
+    fn gumbel(&mut self) -> f32 {
+        -(-self.uniform().ln()).ln() as f32
+    }
+
+    fn normal(&mut self) -> f64 {
+        let (u1, u2) = (self.uniform(), self.uniform());
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+
+    /// Gamma(alpha, 1) sample by the method of Marsaglia and Tsang.
+    pub(super) fn gamma(&mut self, alpha: f64) -> f64 {
+        if alpha < 1.0 {
+            let u = self.uniform();
+            return self.gamma(alpha + 1.0) * u.powf(1.0 / alpha);
+        }
+
+        let d = alpha - 1.0 / 3.0;
+        let c = 1.0 / (9.0 * d).sqrt();
+
+        loop {
+            let x = self.normal();
+            let v = (1.0 + c * x).powi(3);
+
+            if v <= 0.0 {
+                continue;
+            }
+
+            if self.uniform().ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+                return d * v;
+            }
+        }
+    }
+}
@@ -1,7 +1,9 @@
 use std::time::Instant;
 
 use crate::{
-    mcts::{MctsParams, Searcher},
+    chess::{ChessState, GameState},
+    mcts::{Limits, MctsParams, Searcher},
+    networks::ValueNetwork,
     tree::Node,
 };
 
@@ -66,7 +68,7 @@ impl SearchHelpers {
         let t = scalar / (1.0 - params.winning_pst_threshold());
         let base_pst = 1.0 - params.base_pst_adjustment()
             + ((depth as f32) - params.root_pst_adjustment()).powf(-params.depth_pst_adjustment());
-        base_pst + (params.winning_pst_max() - base_pst) * t
+        (base_pst + (params.winning_pst_max() - base_pst) * t) * params.pst_scale()
     }
 
     /// First Play Urgency
@@ -95,7 +97,70 @@ impl SearchHelpers {
     /// This will be overriden by a `go movetime` command,
     /// and a move overhead will be applied to this, so no
     /// need for it here.
+    ///
+    /// A portion of `time_bank`, the time saved by stopping
+    /// previous searches early, is spent on this search.
     pub fn get_time(
+        time: u64,
+        increment: Option<u64>,
+        ply: u32,
+        movestogo: Option<u64>,
+        time_bank: &mut u128,
+        params: &MctsParams,
+    ) -> (u128, u128) {
+        let (opt_time, max_time) = Self::get_base_time(time, increment, ply, movestogo, params);
+
+        // the bank can never hold more than what is left on the clock
+        *time_bank = (*time_bank).min(u128::from(time));
+
+        let max_allowed = (time as f64 * params.tm_max_time()) as u128;
+        let bonus = ((*time_bank as f64 * params.tm_bank_spend()) as u128)
+            .min(max_allowed.saturating_sub(opt_time));
+        *time_bank -= bonus;
+
+        (opt_time + bonus, (max_time + bonus).min(max_allowed))
+    }
+
+    /// Dynamic Contempt
+    ///
+    /// Scales the base `contempt` down as material comes off the
+    /// board, as the root eval moves against the side that wants
+    /// to avoid a draw, and as the clock runs low, so that the
+    /// engine neither presses in lost positions nor in dead-drawn
+    /// endgames.
+    pub fn get_dynamic_contempt(
+        pos: &ChessState,
+        value: &ValueNetwork,
+        params: &MctsParams,
+        time: Option<u64>,
+    ) -> i32 {
+        let contempt = params.contempt();
+
+        if contempt == 0 {
+            return 0;
+        }
+
+        let phase = pos.phase() as f32 / ChessState::MAX_PHASE as f32;
+        let phase_min = params.dyn_contempt_phase_min();
+        let phase_scale = phase_min + (1.0 - phase_min) * phase;
+
+        // with positive contempt, a losing eval means pressing for a
+        // win is futile, and likewise a winning eval with negative
+        // contempt means settling for a draw gives away half a point
+        let eval = pos.get_value(value, params) as f32;
+        let eval_scale = (1.0
+            + contempt.signum() as f32 * eval / params.dyn_contempt_eval_scale() as f32)
+            .clamp(0.0, 1.0);
+
+        let time_scale = time.map_or(1.0, |time| {
+            (time as f32 / params.dyn_contempt_time_full() as f32)
+                .clamp(params.dyn_contempt_time_min(), 1.0)
+        });
+
+        (contempt as f32 * phase_scale * eval_scale * time_scale).round() as i32
+    }
+
+    fn get_base_time(
         time: u64,
         increment: Option<u64>,
         ply: u32,
@@ -143,14 +208,17 @@ impl SearchHelpers {
         }
     }
 
+    /// Returns whether to stop, whether that is earlier than the time
+    /// would allow without a dominant move or smart pruning, and the score.
     pub fn soft_time_cutoff(
         searcher: &Searcher,
         timer: &Instant,
         previous_score: f32,
         best_move_changes: i32,
         nodes: usize,
+        total_iters: usize,
         time: u128,
-    ) -> (bool, f32) {
+    ) -> (bool, bool, f32) {
         let elapsed = timer.elapsed().as_millis();
 
         // Use more time if our eval is falling, and vice versa
@@ -172,16 +240,145 @@ impl SearchHelpers {
         .clamp(searcher.params.tm_bmi2(), searcher.params.tm_bmi3());
 
         // Use less time if our best move has a large percentage of visits, and vice versa
-        let (best_child_ptr, _, _) = searcher.get_best_action(searcher.tree.root_node());
+        let (best_child_ptr, _, _) = searcher.get_best_action(searcher.tree.root_node(), 0);
         let nodes_effort = searcher.tree[best_child_ptr].visits() as f32 / nodes as f32;
         let best_move_visits = (searcher.params.tm_bmv1()
             - ((nodes_effort + searcher.params.tm_bmv2()) * searcher.params.tm_bmv3()).ln_1p()
                 * searcher.params.tm_bmv4())
         .clamp(searcher.params.tm_bmv5(), searcher.params.tm_bmv6());
 
-        let total_time =
-            (time as f32 * falling_eval * best_move_instability * best_move_visits) as u128;
+        // Use much less time if one move dominates both policy and visits
+        let forced = if Self::has_dominant_move(searcher) {
+            searcher.params.forced_time_scale()
+        } else {
+            1.0
+        };
+
+        let unforced_time = time as f32 * falling_eval * best_move_instability * best_move_visits;
+        let total_time = (unforced_time * forced) as u128;
+
+        if elapsed >= total_time {
+            return (true, elapsed < unforced_time as u128, score);
+        }
+
+        // Stop early if the best move can no longer change in the time we have left
+        let iters_per_ms = total_iters as f32 / elapsed.max(1) as f32;
+        let remaining = (total_time - elapsed) as f32 * iters_per_ms;
+
+        let pruned = Self::smart_prune(searcher, total_iters, remaining);
+
+        (pruned, pruned, score)
+    }
+
+    /// Smart Pruning
+    ///
+    /// Returns true if no other root move can overtake the
+    /// best move in visits with the remaining iterations.
+    pub fn smart_prune(searcher: &Searcher, iters: usize, remaining: f32) -> bool {
+        let factor = searcher.params.smart_prune_factor();
+
+        if factor <= 0.0 || iters < searcher.params.smart_prune_min_iters() as usize {
+            return false;
+        }
+
+        let root = searcher.tree.root_node();
+        let node = &searcher.tree[root];
+
+        if node.num_actions() < 2 {
+            return false;
+        }
+
+        let (best_ptr, _, _) = searcher.get_best_action(root, 0);
+        let best_visits = searcher.tree[best_ptr].visits();
+        let first_child_ptr = node.actions();
+        let mut runner_up = 0;
+
+        for action in 0..node.num_actions() {
+            let ptr = first_child_ptr + action;
+
+            if ptr == best_ptr {
+                continue;
+            }
+
+            let visits = searcher.tree[ptr].visits();
+
+            // the move we would play is not the most visited
+            if visits >= best_visits {
+                return false;
+            }
+
+            runner_up = runner_up.max(visits);
+        }
+
+        (best_visits - runner_up) as f32 > remaining / factor
+    }
+
+    /// Shrinks the limits of time-managed searches
+    /// in positions where there is only one move to play.
+    pub fn forced_limits(searcher: &Searcher, mut limits: Limits) -> Limits {
+        if limits.opt_time.is_none() {
+            return limits;
+        }
+
+        let root = searcher.tree.root_node();
+        let node = &searcher.tree[root];
+
+        // a single legal move only needs one iteration to produce a score
+        if node.num_actions() == 1 {
+            limits.max_nodes = 1;
+            return limits;
+        }
+
+        if Self::count_non_losing_moves(searcher.tree.root_position(), searcher) == 1 {
+            let scale = f64::from(searcher.params.forced_time_scale());
+            limits.opt_time = limits.opt_time.map(|t| (t as f64 * scale) as u128);
+            limits.max_time = limits.max_time.map(|t| (t as f64 * scale) as u128);
+        }
+
+        limits
+    }
+
+    /// Counts root moves that are not already proven lost
+    /// and do not allow an immediate checkmate.
+    fn count_non_losing_moves(pos: &ChessState, searcher: &Searcher) -> usize {
+        let node = &searcher.tree[searcher.tree.root_node()];
+        let first_child_ptr = node.actions();
+        let mut count = 0;
+
+        for action in 0..node.num_actions() {
+            let child = &searcher.tree[first_child_ptr + action];
+
+            if let GameState::Won(_) = child.state() {
+                continue;
+            }
+
+            let mut child_pos = pos.clone();
+            child_pos.make_move(child.parent_move());
+
+            let mut mated = false;
+            child_pos.map_legal_moves(|mov| {
+                if !mated {
+                    let mut reply = child_pos.clone();
+                    reply.make_move(mov);
+                    mated = reply.game_state() == GameState::Lost(0);
+                }
+            });
+
+            if !mated {
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    fn has_dominant_move(searcher: &Searcher) -> bool {
+        let root = searcher.tree.root_node();
+        let (best_child_ptr, _, _) = searcher.get_best_action(root, 0);
+        let best_child = &searcher.tree[best_child_ptr];
+        let visit_share = best_child.visits() as f32 / searcher.tree[root].visits().max(1) as f32;
 
-        (elapsed >= total_time, score)
+        best_child.policy() >= searcher.params.forced_policy_mass()
+            && visit_share >= searcher.params.forced_visit_share()
     }
 }
@@ -1,126 +1,269 @@
 use crate::{
-    chess::{ChessState, GameState},
-    tree::{Node, NodePtr},
+    chess::{ChessState, GameState, Move},
+    tree::{HistoryKey, Node, NodePtr},
 };
 
 use super::{SearchHelpers, Searcher};
 
+/// Performs one iteration from the root, returning its depth, or `None`
+/// if the tree is full. With `root_action` set, that root child is
+/// descended into instead of the one picked by PUCT.
 pub fn perform_one(
     searcher: &Searcher,
-    pos: &mut ChessState,
-    ptr: NodePtr,
-    depth: &mut usize,
     thread_id: usize,
-) -> Option<f32> {
-    *depth += 1;
+    root_action: Option<usize>,
+) -> Option<usize> {
+    let tree = searcher.tree;
+    let mut iteration = select(searcher, thread_id, root_action)?;
+
+    // acquire lock to avoid issues with desynced setting of
+    // game state between threads when threads > 1
+    let lock = iteration
+        .path
+        .last()
+        .filter(|_| tree[iteration.leaf].visits() == 0)
+        .map(|step| tree[step.ptr].actions_mut());
+
+    evaluate(searcher, &mut iteration);
+    let u = backpropagate_leaf(searcher, &iteration, thread_id);
+
+    drop(lock);
+
+    Some(backpropagate_path(searcher, iteration, u, thread_id))
+}
+
+struct PathStep {
+    ptr: NodePtr,
+    child_ptr: NodePtr,
+    mov: Move,
+    stm: usize,
+    history_key: HistoryKey,
+    child_hash: u64,
+    child_visits: u64,
+}
 
-    let cur_hash = pos.hash();
-    let mut child_hash: Option<u64> = None;
-    let mut child_visits = 0;
+/// An iteration split into selection, evaluation and backpropagation,
+/// so that the three stages can be scheduled separately.
+pub struct PendingIteration {
+    path: Vec<PathStep>,
+    leaf: NodePtr,
+    pos: ChessState,
+    utility: Option<f32>,
+}
+
+/// Descends from the root to a leaf, expanding nodes on their second
+/// visit. Returns `None` (with virtual losses undone) if the tree is full.
+pub fn select(
+    searcher: &Searcher,
+    thread_id: usize,
+    root_action: Option<usize>,
+) -> Option<PendingIteration> {
     let tree = searcher.tree;
-    let node = &tree[ptr];
+    let mut pos = tree.root_position().clone();
+    let mut ptr = tree.root_node();
+    let mut path: Vec<PathStep> = Vec::new();
 
-    let mut u = if node.is_terminal() || node.visits() == 0 {
-        if node.visits() == 0 {
-            node.set_state(pos.game_state());
-        }
+    loop {
+        let node = &tree[ptr];
 
-        // probe hash table to use in place of network
-        if node.state() == GameState::Ongoing {
-            if let Some(entry) = tree.probe_hash(cur_hash) {
-                entry.q()
-            } else {
-                get_utility(searcher, ptr, pos)
-            }
-        } else {
-            get_utility(searcher, ptr, pos)
+        if node.is_terminal() || node.visits() == 0 {
+            return Some(PendingIteration {
+                path,
+                leaf: ptr,
+                pos,
+                utility: None,
+            });
         }
-    } else {
+
+        let depth = path.len() + 1;
+
         // expand node on the second visit
-        if node.is_not_expanded() {
+        let ready = if node.is_not_expanded() {
             tree.expand_node(
                 ptr,
-                pos,
+                &pos,
                 searcher.params,
                 searcher.policy,
-                *depth,
+                depth,
                 thread_id,
-            )?;
+            )
+        } else {
+            Some(())
         }
-
         // this node has now been accessed so we need to move its
         // children across if they are in the other tree half
-        tree.fetch_children(ptr, thread_id)?;
+        .and_then(|_| tree.fetch_children(ptr, thread_id));
+
+        if ready.is_none() {
+            for step in &path {
+                tree[step.child_ptr].dec_threads();
+            }
+
+            return None;
+        }
 
-        // select action to take via PUCT
+        // select action to take via PUCT, unless the root action is fixed
         let stm = pos.stm();
-        let action = pick_action(searcher, ptr, node);
+        let action = root_action
+            .filter(|&action| path.is_empty() && action < node.num_actions())
+            .unwrap_or_else(|| pick_action(searcher, ptr, node));
 
         let child_ptr = node.actions() + action;
-
         let mov = tree[child_ptr].parent_move();
+        let history_key = HistoryKey::new(&pos, mov);
 
         pos.make_move(mov);
 
-        // capture child hash (value is stored from the side to move at this child)
-        child_hash = Some(pos.hash());
+        path.push(PathStep {
+            ptr,
+            child_ptr,
+            mov,
+            stm,
+            history_key,
+            // value is stored from the side to move at this child
+            child_hash: pos.hash(),
+            child_visits: tree[child_ptr].visits(),
+        });
 
-        child_visits = tree[child_ptr].visits();
         tree[child_ptr].inc_threads();
 
-        // acquire lock to avoid issues with desynced setting of
-        // game state between threads when threads > 1
-        let lock = if tree[child_ptr].visits() == 0 {
-            Some(node.actions_mut())
-        } else {
-            None
-        };
+        ptr = child_ptr;
+    }
+}
 
-        // descend further
-        let maybe_u = perform_one(searcher, pos, child_ptr, depth, thread_id);
+/// Resolves the game state of a newly visited leaf, and evaluates it
+/// with the hash table or value network.
+pub fn evaluate(searcher: &Searcher, iteration: &mut PendingIteration) {
+    let tree = searcher.tree;
+    let (ptr, pos) = (iteration.leaf, &iteration.pos);
+    let node = &tree[ptr];
 
-        drop(lock);
+    if node.visits() == 0 {
+        node.set_state(leaf_state(searcher, ptr, pos));
+    }
 
-        tree[child_ptr].dec_threads();
+    // probe hash table, then experience near the root, to use in place of network
+    let probed = if node.state() == GameState::Ongoing {
+        tree.probe_hash(pos.hash())
+            .map(|entry| entry.q())
+            .or_else(|| {
+                let depth = iteration.path.len();
+                searcher
+                    .experience
+                    .and_then(|experience| experience.probe(pos.hash(), depth))
+            })
+    } else {
+        None
+    };
+
+    iteration.utility = Some(probed.unwrap_or_else(|| get_utility(searcher, ptr, pos)));
+}
+
+/// Backpropagates an evaluated iteration, returning its depth.
+pub fn backpropagate(searcher: &Searcher, iteration: PendingIteration, thread_id: usize) -> usize {
+    let u = backpropagate_leaf(searcher, &iteration, thread_id);
+    backpropagate_path(searcher, iteration, u, thread_id)
+}
+
+fn backpropagate_leaf(searcher: &Searcher, iteration: &PendingIteration, thread_id: usize) -> f32 {
+    let tree = searcher.tree;
+    let u = iteration
+        .utility
+        .expect("iteration must be evaluated first");
+
+    tree.push_hash(iteration.pos.hash(), u, 1);
+
+    // flip perspective and backpropagate
+    tree.update_node_stats(iteration.leaf, 1.0 - u, thread_id);
+    1.0 - u
+}
 
-        let u = maybe_u?;
+fn backpropagate_path(
+    searcher: &Searcher,
+    iteration: PendingIteration,
+    mut u: f32,
+    thread_id: usize,
+) -> usize {
+    let tree = searcher.tree;
 
-        if tree[child_ptr].state() == GameState::Ongoing {
-            tree.update_butterfly(stm, mov, u, searcher.params);
+    for step in iteration.path.iter().rev() {
+        let child = &tree[step.child_ptr];
+
+        child.dec_threads();
+
+        if child.state() == GameState::Ongoing {
+            tree.update_butterfly(step.stm, step.mov, u, searcher.params);
+            tree.update_history(&step.history_key, u, searcher.params);
         }
 
-        tree.propogate_proven_mates(ptr, tree[child_ptr].state());
+        tree.propogate_proven_mates(step.ptr, child.state());
 
-        u
-    };
+        // `u` here is from the parent's perspective, so flip for the child
+        tree.push_hash(step.child_hash, 1.0 - u, step.child_visits);
+        u = 1.0 - u;
+        tree.update_node_stats(step.ptr, u, thread_id);
+    }
 
-    // store value for the side to move at the visited node in TT
-    if let Some(h) = child_hash {
-        // `u` here is from the current node's perspective, so flip for the child
-        tree.push_hash(h, 1.0 - u, child_visits);
-    } else {
-        tree.push_hash(cur_hash, u, 1);
+    iteration.path.len() + 1
+}
+
+/// Game state of a newly visited node, proven by the
+/// endgame tablebases where possible.
+fn leaf_state(searcher: &Searcher, ptr: NodePtr, pos: &ChessState) -> GameState {
+    let state = pos.game_state();
+
+    // the root must stay unproven so that a move is searched
+    if state != GameState::Ongoing || ptr == searcher.tree.root_node() {
+        return state;
     }
 
-    // flip perspective and backpropagate
-    u = 1.0 - u;
-    tree.update_node_stats(ptr, u, thread_id);
-    Some(u)
+    searcher
+        .tablebases
+        .and_then(|tbs| tbs.probe(&pos.board()))
+        .unwrap_or(state)
 }
 
 fn get_utility(searcher: &Searcher, ptr: NodePtr, pos: &ChessState) -> f32 {
     match searcher.tree[ptr].state() {
-        GameState::Ongoing => pos.get_value_wdl(
-            searcher.value,
-            searcher.params,
-            searcher.tree.root_position().stm(),
-        ),
-        GameState::Draw => 0.5,
+        GameState::Ongoing => {
+            let value = pos.get_value_wdl(
+                searcher.value,
+                searcher.params,
+                searcher.tree.root_position().stm(),
+            );
+
+            verify_tactics(searcher, pos, value)
+        }
+        GameState::Draw => searcher
+            .params
+            .draw_utility(pos.stm(), searcher.tree.root_position().stm()),
         GameState::Lost(_) => 0.0,
         GameState::Won(_) => 1.0,
     }
 }
 
+/// Corrects the network value of a leaf when a shallow capture search
+/// finds that the side to move can win material.
+fn verify_tactics(searcher: &Searcher, pos: &ChessState, value: f32) -> f32 {
+    let budget = searcher.params.qsearch_budget();
+
+    if budget <= 0 {
+        return value;
+    }
+
+    let gain = pos.tactical_gain(budget as usize);
+
+    if gain < searcher.params.qsearch_margin() {
+        return value;
+    }
+
+    // shift the value in logit space, as though the material was already won
+    let value = value.clamp(1e-4, 1.0 - 1e-4);
+    let logit = (value / (1.0 - value)).ln() + gain as f32 / searcher.params.qsearch_scale();
+
+    1.0 / (1.0 + (-logit).exp())
+}
+
 fn pick_action(searcher: &Searcher, ptr: NodePtr, node: &Node) -> usize {
     let is_root = ptr == searcher.tree.root_node();
 
@@ -12,6 +12,10 @@ impl<T> Param<T> {
 }
 
 impl Param<i32> {
+    fn get(&self) -> i32 {
+        self.val
+    }
+
     fn set(&mut self, val: i32) {
         self.val = val.clamp(self.min, self.max);
     }
@@ -32,6 +36,10 @@ impl Param<i32> {
 }
 
 impl Param<f32> {
+    fn get(&self) -> i32 {
+        (self.val * 1000.0).round() as i32
+    }
+
     fn set(&mut self, val: i32) {
         let actual = val as f32 / 1000.0;
         self.val = actual.clamp(self.min, self.max);
@@ -61,6 +69,10 @@ impl Param<f32> {
 }
 
 impl Param<f64> {
+    fn get(&self) -> i32 {
+        (self.val * 1000.0).round() as i32
+    }
+
     fn set(&mut self, val: i32) {
         let actual = val as f64 / 1000.0;
         self.val = actual.clamp(self.min, self.max);
@@ -115,11 +127,19 @@ macro_rules! make_mcts_params {
                 $(self.$name.info(stringify!($name));)*
             }
 
-            pub fn set(&mut self, name: &str, val: i32) {
+            /// Sets the parameter called `name`, returning whether it exists.
+            pub fn set(&mut self, name: &str, val: i32) -> bool {
                 match name {
                     $(stringify!($name) => self.$name.set(val),)*
-                    _ => println!("unknown option!"),
+                    _ => return false,
                 }
+
+                true
+            }
+
+            /// Every parameter with its value in the units of [`Self::set`].
+            pub fn values(&self) -> Vec<(&'static str, i32)> {
+                vec![$((stringify!($name), self.$name.get()),)*]
             }
 
             pub fn list_spsa(&self) {
@@ -168,6 +188,7 @@ make_mcts_params! {
     tm_bonus_value1: f64 = 0.452, 0.1, 2.0, 0.05, 0.002;
     tm_max_time: f64 = 0.881, 0.400, 0.990, 0.085, 0.002;
     tm_mtg: i32 = 28, 10, 60, 3, 0.002;
+    tm_bank_spend: f64 = 0.25, 0.0, 1.0, 0.025, 0.002;
     tm_falling_eval1: f32 = 0.054, 0.0, 0.2, 0.007, 0.002;
     tm_falling_eval2: f32 = 0.724, 0.1, 1.0, 0.06, 0.002;
     tm_falling_eval3: f32 = 1.633, 0.1, 3.0, 0.18, 0.002;
@@ -182,9 +203,51 @@ make_mcts_params! {
     tm_bmv6: f32 = 1.894, 0.1, 3.0, 0.15, 0.002;
     butterfly_reduction_factor: i32 = 8192, 1, 65536, 819, 0.002;
     butterfly_policy_divisor: i32 = 16384, 1, 131072, 1638, 0.002;
+    piece_to_bonus_weight: f32 = 0.0, 0.0, 4.0, 0.1, 0.002;
+    piece_to_reduction_factor: i32 = 8192, 1, 65536, 819, 0.002;
+    piece_to_policy_divisor: i32 = 16384, 1, 131072, 1638, 0.002;
+    cont1_bonus_weight: f32 = 0.0, 0.0, 4.0, 0.1, 0.002;
+    cont1_reduction_factor: i32 = 8192, 1, 65536, 819, 0.002;
+    cont1_policy_divisor: i32 = 16384, 1, 131072, 1638, 0.002;
+    cont2_bonus_weight: f32 = 0.0, 0.0, 4.0, 0.1, 0.002;
+    cont2_reduction_factor: i32 = 8192, 1, 65536, 819, 0.002;
+    cont2_policy_divisor: i32 = 16384, 1, 131072, 1638, 0.002;
     policy_top_p: f32 = 0.7, 0.1, 1.0, 0.05, 0.002;
     min_policy_actions: i32 = 6, 1, 32, 1, 0.002;
     visit_threshold_power: i32 = 3, 0, 8, 1, 0.002;
     virtual_loss_weight: f64 = 2.5, 1.0, 5.0, 0.25, 0.002;
+    smart_prune_factor: f32 = 0.0, 0.0, 4.0, 0.1, 0.002;
+    smart_prune_min_iters: i32 = 1024, 0, 65536, 128, 0.002;
+    smart_prune_kld: f32 = 0.0, 0.0, 10.0, 0.05, 0.002;
+    forced_time_scale: f32 = 0.05, 0.0, 1.0, 0.005, 0.002;
+    forced_policy_mass: f32 = 0.95, 0.5, 1.0, 0.01, 0.002;
+    forced_visit_share: f32 = 0.9, 0.5, 1.0, 0.01, 0.002;
+    gumbel_actions: i32 = 16, 1, 64, 2, 0.002;
+    gumbel_c_visit: f32 = 50.0, 1.0, 200.0, 5.0, 0.002;
+    gumbel_c_scale: f32 = 1.0, 0.1, 10.0, 0.1, 0.002;
+    qsearch_budget: i32 = 0, 0, 256, 8, 0.002;
+    qsearch_margin: i32 = 100, 0, 1000, 25, 0.002;
+    qsearch_scale: f32 = 400.0, 50.0, 2000.0, 40.0, 0.002;
+    root_parallel_noise: f32 = 0.1, 0.0, 0.5, 0.025, 0.002;
+    dyn_contempt_phase_min: f32 = 0.25, 0.0, 1.0, 0.05, 0.002;
+    dyn_contempt_eval_scale: i32 = 400, 50, 2000, 40, 0.002;
+    dyn_contempt_time_full: i32 = 60000, 1000, 600000, 6000, 0.002;
+    dyn_contempt_time_min: f32 = 0.5, 0.0, 1.0, 0.05, 0.002;
     contempt: i32 = 0, -1000, 1000, 10, 0.0; //Do not tune this value!
+    draw_score: i32 = 0, -100, 100, 5, 0.0; //Do not tune this value!
+    pst_scale: f32 = 1.0, 1.0, 10.0, 0.1, 0.0; //Do not tune this value!
+}
+
+impl MctsParams {
+    /// Utility of a draw for `stm`, where `draw_score` is the percentage
+    /// of the way from a half point to a win for the root side to move.
+    pub fn draw_utility(&self, stm: usize, root_stm: usize) -> f32 {
+        let root = 0.5 + self.draw_score() as f32 / 200.0;
+
+        if stm == root_stm {
+            root
+        } else {
+            1.0 - root
+        }
+    }
 }
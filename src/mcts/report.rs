@@ -0,0 +1,199 @@
+use std::{fmt, sync::atomic::Ordering};
+
+use crate::{
+    chess::{EvalWdl, GameState},
+    json::Json,
+};
+
+use super::{Searcher, JSON_OUTPUT};
+
+/// Score of a search, from the perspective of the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Score {
+    Cp(i32),
+    /// Moves until mate, negative if getting mated.
+    Mate(i32),
+}
+
+impl Score {
+    /// Converts an expected score, where scores above one and below
+    /// zero mark proven wins and losses, mating along the `pv`.
+    pub fn from_q(q: f32, pv_len: usize) -> Self {
+        if q > 1.0 {
+            Self::Mate(pv_len.div_ceil(2) as i32)
+        } else if q < 0.0 {
+            Self::Mate(-((pv_len / 2) as i32))
+        } else {
+            Self::Cp(Searcher::get_cp(q).round() as i32)
+        }
+    }
+}
+
+impl Score {
+    pub fn to_json(self) -> Json {
+        match self {
+            Self::Cp(cp) => Json::object([("cp", cp.into())]),
+            Self::Mate(moves) => Json::object([("mate", moves.into())]),
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Cp(cp) => write!(f, "cp {cp}"),
+            Self::Mate(moves) => write!(f, "mate {moves}"),
+        }
+    }
+}
+
+/// Progress of a search, displayed as a UCI `info` line.
+#[derive(Clone, Debug)]
+pub struct SearchInfo {
+    /// Average and maximum depth, unknown when merging several trees.
+    pub depth: Option<(usize, usize)>,
+    pub score: Score,
+    /// Expected outcome, combining the score with the draw rate of the
+    /// root evaluation, only in the last report or with JSON output.
+    pub wdl: Option<EvalWdl>,
+    pub time: u128,
+    /// Nodes, or playouts if `report_iters` is set.
+    pub nodes: usize,
+    pub iters: usize,
+    pub nps: f32,
+    /// Permille of the tree in use.
+    pub hashfull: usize,
+    /// Principal variation in UCI notation.
+    pub pv: Vec<String>,
+    /// Statistics of every root move, from best to worst, empty unless
+    /// in the last report or with JSON output.
+    pub root_moves: Vec<RootMove>,
+}
+
+impl SearchInfo {
+    pub fn to_json(&self) -> Json {
+        let (depth, seldepth) = self.depth.unzip();
+
+        let root_moves = self
+            .root_moves
+            .iter()
+            .map(|root_move| {
+                Json::object([
+                    ("move", root_move.mov.as_str().into()),
+                    ("visits", root_move.visits.into()),
+                    ("q", root_move.q.into()),
+                    ("policy", root_move.policy.into()),
+                    ("state", root_move.state.to_string().into()),
+                ])
+            })
+            .collect::<Vec<_>>();
+
+        Json::object([
+            ("type", "info".into()),
+            ("depth", depth.into()),
+            ("seldepth", seldepth.into()),
+            ("nodes", self.nodes.into()),
+            ("iters", self.iters.into()),
+            ("nps", self.nps.round().into()),
+            ("time", self.time.into()),
+            ("score", self.score.to_json()),
+            (
+                "wdl",
+                self.wdl.map(|wdl| vec![wdl.win, wdl.draw, wdl.loss]).into(),
+            ),
+            ("pv", self.pv.clone().into()),
+            ("hashfull", self.hashfull.into()),
+            ("root_moves", Json::Array(root_moves)),
+        ])
+    }
+}
+
+impl fmt::Display for SearchInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "info ")?;
+
+        if let Some((depth, seldepth)) = self.depth {
+            write!(f, "depth {depth} seldepth {seldepth} ")?;
+        }
+
+        write!(
+            f,
+            "score {} time {} nodes {} nps {:.0} pv",
+            self.score, self.time, self.nodes, self.nps
+        )?;
+
+        for mov in &self.pv {
+            write!(f, " {mov}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Statistics of a root move after a search.
+#[derive(Clone, Debug)]
+pub struct RootMove {
+    /// Move in UCI notation.
+    pub mov: String,
+    pub visits: u64,
+    pub score: Score,
+    /// Average value of the move for the side playing it.
+    pub q: f32,
+    /// Prior given to the move by the policy network.
+    pub policy: f32,
+    /// Proven result of the position after the move, for the side to
+    /// move there, written as in `report_moves`: `O` for ongoing, `D`
+    /// for a draw, and `W<n>` or `L<n>` for a win or loss in `n` plies.
+    pub state: GameState,
+    /// Principal variation in UCI notation, starting with the move.
+    pub pv: Vec<String>,
+}
+
+/// Output of a search, displayed as a UCI line.
+#[derive(Clone, Debug)]
+pub enum SearchEvent {
+    Info(SearchInfo),
+    /// Anything else worth telling the user, as an `info string`.
+    Message(String),
+    /// The move played, in UCI notation.
+    BestMove(String),
+}
+
+impl SearchEvent {
+    /// The event as a JSON object, with its kind in the `type` field.
+    pub fn to_json(&self) -> Json {
+        match self {
+            Self::Info(info) => info.to_json(),
+            Self::Message(message) => Json::object([
+                ("type", "message".into()),
+                ("message", message.as_str().into()),
+            ]),
+            Self::BestMove(mov) => {
+                Json::object([("type", "bestmove".into()), ("move", mov.as_str().into())])
+            }
+        }
+    }
+}
+
+impl fmt::Display for SearchEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Info(info) => write!(f, "{info}"),
+            Self::Message(message) => write!(f, "info string {message}"),
+            Self::BestMove(mov) => write!(f, "bestmove {mov}"),
+        }
+    }
+}
+
+/// Receives the output of a search, in place of printing it.
+pub type Reporter<'a> = dyn Fn(SearchEvent) + Sync + 'a;
+
+/// Reporter printing every event to stdout, as newline
+/// delimited JSON if [`JSON_OUTPUT`] is set.
+pub fn print_event(event: SearchEvent) {
+    if JSON_OUTPUT.load(Ordering::Relaxed) {
+        println!("{}", event.to_json());
+    } else {
+        println!("{event}");
+    }
+}
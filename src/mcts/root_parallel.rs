@@ -0,0 +1,502 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    chess::{GameState, Move},
+    experience::Experience,
+    networks::{PolicyNetwork, ValueNetwork},
+    tablebase::Tablebases,
+    tree::Tree,
+};
+
+use super::{
+    gumbel::Rand, print_event, Limits, MctsParams, Reporter, RootMove, Score, SearchEvent,
+    SearchInfo, Searcher, JSON_OUTPUT,
+};
+
+/// Dirichlet(alpha) noise mixed into the root policy with weight
+/// `epsilon`, so that otherwise identical searches explore different moves.
+#[derive(Clone, Copy, Debug)]
+pub struct RootNoise {
+    pub alpha: f32,
+    pub epsilon: f32,
+    pub seed: u64,
+}
+
+impl Searcher<'_> {
+    pub(super) fn add_root_noise(&self, noise: RootNoise) {
+        let node = &self.tree[self.tree.root_node()];
+
+        if node.num_actions() <= 1 {
+            return;
+        }
+
+        let first_child_ptr = node.actions();
+        let mut rng = Rand::new(noise.seed);
+
+        // normalised gamma samples are dirichlet distributed
+        let samples = (0..node.num_actions())
+            .map(|_| rng.gamma(f64::from(noise.alpha)))
+            .collect::<Vec<_>>();
+        let total = samples.iter().sum::<f64>();
+
+        for (action, sample) in samples.into_iter().enumerate() {
+            // guard against every sample underflowing for small alpha
+            let sample = if total > 0.0 {
+                (sample / total) as f32
+            } else {
+                1.0 / node.num_actions() as f32
+            };
+
+            let child = &self.tree[first_child_ptr + action];
+            let mixed = (1.0 - noise.epsilon) * child.policy() + noise.epsilon * sample;
+            child.set_policy(mixed);
+        }
+    }
+}
+
+/// Statistics of a root child searched outside of this process.
+#[derive(Clone, Copy, Debug)]
+pub struct RootChildStats {
+    pub mov: Move,
+    pub visits: u64,
+    pub q: f32,
+    pub state: GameState,
+}
+
+/// Searches of the same position running elsewhere, whose root
+/// statistics are merged with those of the local trees.
+pub trait RemoteSearch {
+    /// Number of independent searches, each counting as one group.
+    fn searches(&self) -> usize;
+
+    /// Starts every search with `params`, the `i`th one with noise
+    /// seeded by `seed + i`. Lost searches are reported to `reporter`.
+    fn start(
+        &mut self,
+        params: &MctsParams,
+        limits: &Limits,
+        noise: f32,
+        seed: u64,
+        reporter: &Reporter,
+    );
+
+    /// Root children and node count of all searches so far, where
+    /// the same move may appear once per search.
+    fn stats(&self) -> (Vec<RootChildStats>, usize);
+
+    /// Stops every search and waits for its final statistics.
+    fn finish(&mut self, reporter: &Reporter) -> (Vec<RootChildStats>, usize);
+}
+
+/// Root statistics of a move, summed over every tree.
+struct MergedChild {
+    mov: Move,
+    visits: u64,
+    total_q: f64,
+    state: GameState,
+    // tree in which the move has the most visits, used for the pv
+    best_tree: usize,
+    best_visits: u64,
+}
+
+impl MergedChild {
+    fn q(&self) -> f32 {
+        if self.visits == 0 {
+            0.0
+        } else {
+            (self.total_q / self.visits as f64) as f32
+        }
+    }
+
+    fn key(&self, draw: f32) -> f32 {
+        Searcher::best_child_key(self.visits, self.state, self.q(), draw)
+    }
+}
+
+/// Root-parallel search: the threads are split into one group per tree,
+/// each group searches its own tree without sharing any nodes, and the
+/// root statistics of all trees are merged to pick the move.
+///
+/// Every group but the first adds differently seeded noise to its root
+/// policy, so that the trees do not all explore the same lines. Node
+/// limits are split between the groups, and the first group to reach a
+/// limit stops the others.
+///
+/// Remote searches count as further groups, and are stopped as soon
+/// as the local ones finish. Merged progress is sent to `reporter`.
+#[allow(clippy::too_many_arguments)]
+pub fn search_root_parallel(
+    trees: &[&Tree],
+    params: &MctsParams,
+    policy: &PolicyNetwork,
+    value: &ValueNetwork,
+    abort: &AtomicBool,
+    tablebases: Option<&Tablebases>,
+    experience: Option<&Experience>,
+    threads: usize,
+    limits: Limits,
+    reporter: Option<&Reporter>,
+    update_nodes: &mut usize,
+    mut remote: Option<&mut dyn RemoteSearch>,
+) -> (Move, f32) {
+    let timer = Instant::now();
+    let messages = reporter.unwrap_or(&print_event);
+    let groups = trees.len() + remote.as_ref().map_or(0, |remote| remote.searches());
+
+    let searchers = trees
+        .iter()
+        .map(|tree| {
+            Searcher::new(tree, params, policy, value, abort, tablebases)
+                .with_experience(experience)
+        })
+        .collect::<Vec<_>>();
+
+    let seed = if limits.deterministic {
+        0
+    } else {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Guaranteed increasing.")
+            .as_nanos() as u64
+    };
+
+    let group_limits = (0..trees.len())
+        .map(|group| {
+            let mut group_limits = limits;
+
+            if limits.max_nodes != usize::MAX {
+                group_limits.max_nodes = (limits.max_nodes / groups).max(1);
+            }
+
+            if group > 0 {
+                group_limits.root_noise = Some(RootNoise {
+                    alpha: 1.0,
+                    epsilon: params.root_parallel_noise(),
+                    seed: seed.wrapping_add(group as u64),
+                });
+            }
+
+            group_limits
+        })
+        .collect::<Vec<_>>();
+
+    if let Some(remote) = remote.as_mut() {
+        let mut remote_limits = limits;
+
+        if limits.max_nodes != usize::MAX {
+            remote_limits.max_nodes = (limits.max_nodes / groups).max(1);
+        }
+
+        remote.start(
+            params,
+            &remote_limits,
+            params.root_parallel_noise(),
+            seed.wrapping_add(trees.len() as u64),
+            messages,
+        );
+    }
+
+    let start_visits = trees
+        .iter()
+        .map(|tree| root_visits(tree))
+        .collect::<Vec<_>>();
+    let finished = AtomicUsize::new(0);
+    let mut nodes = vec![0; trees.len()];
+
+    thread::scope(|s| {
+        for (group, (searcher, nodes)) in searchers.iter().zip(nodes.iter_mut()).enumerate() {
+            let group_threads =
+                (threads / trees.len() + usize::from(group < threads % trees.len())).max(1);
+            let (group_limits, finished) = (group_limits[group], &finished);
+
+            s.spawn(move || {
+                searcher.search(
+                    group_threads,
+                    group_limits,
+                    false,
+                    nodes,
+                    #[cfg(feature = "datagen")]
+                    false,
+                    #[cfg(feature = "datagen")]
+                    1.0,
+                );
+
+                finished.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+
+        let Some(reporter) = reporter else {
+            return;
+        };
+
+        let mut last_output = Instant::now();
+
+        while finished.load(Ordering::Relaxed) < trees.len() {
+            thread::sleep(Duration::from_millis(10));
+
+            if last_output.elapsed().as_secs() >= 1 {
+                // exact node counts are only known once the groups
+                // finish, so root visits stand in for them until then
+                let iters = trees
+                    .iter()
+                    .zip(&start_visits)
+                    .map(|(tree, &start)| root_visits(tree).saturating_sub(start))
+                    .sum::<u64>();
+
+                let (remote_children, remote_nodes) = remote
+                    .as_ref()
+                    .map_or((Vec::new(), 0), |remote| remote.stats());
+
+                report(
+                    &searchers,
+                    &remote_children,
+                    &timer,
+                    iters as usize + remote_nodes,
+                    reporter,
+                    false,
+                );
+                last_output = Instant::now();
+            }
+        }
+    });
+
+    let (remote_children, remote_nodes) =
+        remote.map_or((Vec::new(), 0), |remote| remote.finish(messages));
+
+    let total_nodes = nodes.iter().sum::<usize>() + remote_nodes;
+    *update_nodes += total_nodes;
+
+    if let Some(reporter) = reporter {
+        report(
+            &searchers,
+            &remote_children,
+            &timer,
+            total_nodes,
+            reporter,
+            true,
+        );
+    }
+
+    match best_merged_child(&searchers, &remote_children) {
+        Some(best) => (best.mov, best.q()),
+        None => {
+            let searcher = &searchers[0];
+            let (_, mov, q) = searcher.get_best_action(searcher.tree.root_node(), 0);
+            (mov, q)
+        }
+    }
+}
+
+fn root_visits(tree: &Tree) -> u64 {
+    if tree.is_empty() {
+        0
+    } else {
+        tree[tree.root_node()].visits()
+    }
+}
+
+fn merge(searchers: &[Searcher], remote_children: &[RootChildStats]) -> Vec<MergedChild> {
+    let mut merged: Vec<MergedChild> = Vec::new();
+
+    let mut add = |stats: RootChildStats, tree: Option<usize>| {
+        let pos = match merged.iter().position(|entry| entry.mov == stats.mov) {
+            Some(pos) => pos,
+            None => {
+                merged.push(MergedChild {
+                    mov: stats.mov,
+                    visits: 0,
+                    total_q: 0.0,
+                    state: GameState::Ongoing,
+                    best_tree: 0,
+                    best_visits: 0,
+                });
+                merged.len() - 1
+            }
+        };
+
+        let entry = &mut merged[pos];
+        entry.visits += stats.visits;
+        entry.total_q += f64::from(stats.q) * stats.visits as f64;
+
+        if entry.state == GameState::Ongoing {
+            entry.state = stats.state;
+        }
+
+        if let Some(idx) = tree.filter(|_| stats.visits > entry.best_visits) {
+            entry.best_tree = idx;
+            entry.best_visits = stats.visits;
+        }
+    };
+
+    for (idx, searcher) in searchers.iter().enumerate() {
+        let tree = searcher.tree;
+
+        if tree.is_empty() {
+            continue;
+        }
+
+        let node = &tree[tree.root_node()];
+        let first_child_ptr = node.actions();
+
+        for action in 0..node.num_actions() {
+            let child = &tree[first_child_ptr + action];
+
+            let stats = RootChildStats {
+                mov: child.parent_move(),
+                visits: child.visits(),
+                q: child.q(),
+                state: child.state(),
+            };
+
+            add(stats, Some(idx));
+        }
+    }
+
+    // remote searches may have been sent another position, so only
+    // moves that are legal here are trusted
+    let root = searchers[0].tree.root_position();
+    let mut legal = Vec::new();
+    root.map_legal_moves(|mov| legal.push(mov));
+
+    for &stats in remote_children {
+        if legal.contains(&stats.mov) {
+            add(stats, None);
+        }
+    }
+
+    merged
+}
+
+fn best_merged_child(
+    searchers: &[Searcher],
+    remote_children: &[RootChildStats],
+) -> Option<MergedChild> {
+    let draw = searchers[0].draw_utility(0);
+
+    merge(searchers, remote_children)
+        .into_iter()
+        .max_by(|a, b| a.key(draw).total_cmp(&b.key(draw)))
+}
+
+fn report(
+    searchers: &[Searcher],
+    remote_children: &[RootChildStats],
+    timer: &Instant,
+    nodes: usize,
+    reporter: &Reporter,
+    last: bool,
+) {
+    let detailed = last || JSON_OUTPUT.load(Ordering::Relaxed);
+    let draw = searchers[0].draw_utility(0);
+    let mut merged = merge(searchers, remote_children);
+
+    // ties go to the last child, as in `best_merged_child`
+    merged.sort_by(|a, b| a.key(draw).total_cmp(&b.key(draw)));
+    merged.reverse();
+
+    let root_move = |child: &MergedChild| {
+        let searcher = &searchers[child.best_tree];
+        let pv = merged_pv(searcher, child.mov);
+        let score = Searcher::child_score(child.state, child.q());
+        let tree = searcher.tree;
+
+        let policy = if tree.is_empty() {
+            0.0
+        } else {
+            let node = &tree[tree.root_node()];
+
+            (0..node.num_actions())
+                .map(|action| &tree[node.actions() + action])
+                .find(|child_node| child_node.parent_move() == child.mov)
+                .map_or(0.0, |child_node| child_node.policy())
+        };
+
+        let (score, pv) = (
+            Score::from_q(score, pv.len()),
+            pv.into_iter()
+                .map(|mov| tree.root_position().conv_mov_to_str(mov))
+                .collect::<Vec<_>>(),
+        );
+
+        RootMove {
+            mov: tree.root_position().conv_mov_to_str(child.mov),
+            visits: child.visits,
+            score,
+            q: child.q(),
+            policy,
+            state: child.state,
+            pv,
+        }
+    };
+
+    let Some(best) = merged.first() else {
+        return;
+    };
+
+    let best_move = root_move(best);
+    let root_moves = if detailed {
+        merged.iter().map(root_move).collect()
+    } else {
+        Vec::new()
+    };
+
+    let elapsed = timer.elapsed();
+
+    let info = SearchInfo {
+        depth: None,
+        score: best_move.score,
+        wdl: detailed.then(|| searchers[0].root_wdl(Searcher::child_score(best.state, best.q()))),
+        time: elapsed.as_millis(),
+        nodes,
+        iters: nodes,
+        nps: nodes as f32 / elapsed.as_secs_f32(),
+        hashfull: searchers
+            .iter()
+            .map(|searcher| searcher.tree.hashfull())
+            .max()
+            .unwrap_or(0),
+        pv: best_move.pv,
+        root_moves,
+    };
+
+    reporter(SearchEvent::Info(info));
+}
+
+/// The merged best move, continued by the best line of the tree
+/// that searched it the most.
+fn merged_pv(searcher: &Searcher, mov: Move) -> Vec<Move> {
+    let tree = searcher.tree;
+    let node = &tree[tree.root_node()];
+    let first_child_ptr = node.actions();
+    let half = tree.half() > 0;
+
+    let mut pv = vec![mov];
+
+    let Some(mut ptr) = (0..node.num_actions())
+        .map(|action| first_child_ptr + action)
+        .find(|&ptr| tree[ptr].parent_move() == mov)
+    else {
+        return pv;
+    };
+
+    loop {
+        if !tree[ptr].has_children() || searcher.get_best_child(ptr, pv.len()) == usize::MAX {
+            break;
+        }
+
+        let (child_ptr, child_mov, _) = searcher.get_best_action(ptr, pv.len());
+
+        if child_ptr.is_null() || child_ptr.half() != half || tree[child_ptr].visits() == 0 {
+            break;
+        }
+
+        pv.push(child_mov);
+        ptr = child_ptr;
+    }
+
+    pv
+}
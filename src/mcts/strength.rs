@@ -0,0 +1,85 @@
+use super::{gumbel::Rand, Limits, MctsParams};
+
+/// Lowest and highest `UCI_Elo` supported.
+pub const MIN_ELO: i32 = 1000;
+pub const MAX_ELO: i32 = 3000;
+
+// (elo, nodes, policy temperature scale, move temperature)
+//
+// Initial estimates, not yet measured. Calibrate with the embedded
+// networks by running `make calibrate-strength`, which plays 256 games
+// between each pair of levels 200 apart, then replace these rows with
+// the ones it prints, keeping those at MIN_ELO and MAX_ELO, and record
+// the measured differences here.
+const ANCHORS: [(i32, f32, f32, f32); 6] = [
+    (1000, 16.0, 4.0, 2.0),
+    (1400, 32.0, 3.0, 1.5),
+    (1800, 64.0, 2.2, 1.0),
+    (2200, 160.0, 1.6, 0.6),
+    (2600, 400.0, 1.2, 0.3),
+    (3000, 1500.0, 1.0, 0.1),
+];
+
+/// Temperature for sampling the root move in proportion to its visits,
+/// see [`crate::tree::Tree::get_best_child_temp`].
+#[derive(Clone, Copy, Debug)]
+pub struct MoveTemperature {
+    pub temperature: f32,
+    pub seed: u64,
+}
+
+impl MoveTemperature {
+    /// Uniform sample in `[0, 1)` to pick the move with.
+    pub fn sample(&self) -> f64 {
+        Rand::new(self.seed).uniform()
+    }
+}
+
+/// Weakened search settings for a target Elo.
+#[derive(Clone, Copy, Debug)]
+pub struct Strength {
+    pub elo: i32,
+    pub max_nodes: usize,
+    pub pst_scale: f32,
+    pub temperature: f32,
+}
+
+impl Strength {
+    /// Interpolates between calibrated levels, geometrically for the
+    /// node limit and linearly for the temperatures.
+    pub fn from_elo(elo: i32) -> Self {
+        let elo = elo.clamp(MIN_ELO, MAX_ELO);
+
+        let upper = ANCHORS
+            .iter()
+            .position(|&(anchor, ..)| anchor >= elo)
+            .unwrap()
+            .max(1);
+
+        let (elo0, nodes0, pst0, temp0) = ANCHORS[upper - 1];
+        let (elo1, nodes1, pst1, temp1) = ANCHORS[upper];
+        let t = (elo - elo0) as f32 / (elo1 - elo0) as f32;
+
+        let lerp = |a: f32, b: f32| a + (b - a) * t;
+
+        Self {
+            elo,
+            max_nodes: (nodes0.ln() + (nodes1.ln() - nodes0.ln()) * t)
+                .exp()
+                .round() as usize,
+            pst_scale: lerp(pst0, pst1),
+            temperature: lerp(temp0, temp1),
+        }
+    }
+
+    /// Weakens a search, where `seed` decides the sampled move.
+    pub fn apply(&self, limits: &mut Limits, params: &mut MctsParams, seed: u64) {
+        limits.max_nodes = limits.max_nodes.min(self.max_nodes);
+        limits.move_temperature = Some(MoveTemperature {
+            temperature: self.temperature,
+            seed,
+        });
+
+        params.set("pst_scale", (self.pst_scale * 1000.0).round() as i32);
+    }
+}
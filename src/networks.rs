@@ -1,4 +1,6 @@
 pub mod common;
+#[cfg(feature = "embed")]
+pub mod embedded;
 pub mod policy;
 pub mod value;
 
@@ -0,0 +1,564 @@
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    chess::ChessState,
+    engine::{Engine, GoOptions, SearchResult},
+    json::Json,
+    networks::{PolicyNetwork, ValueNetwork},
+};
+
+// Analysis is served over HTTP with JSON bodies, one request per
+// connection. `POST /analyse` takes
+//
+//     {"id": "a", "fen": "...", "moves": ["e2e4"], "nodes": 100000,
+//      "movetime": 1000, "depth": 10, "multipv": 3, "contempt": 0}
+//
+// where every field is optional except for at least one of the limits,
+// and `fen` defaults to the starting position. The search is the same
+// whatever `multipv` is, it only picks how many of the most visited root
+// moves are listed under `multipv` in the reply. Searches share the tree
+// unless `contempt` differs from the previous request. It replies once the search
+// has finished, which may be after earlier requests in the queue, with
+//
+//     {"id": "a", "bestmove": "e2e4", "score": {"cp": 30},
+//      "wdl": [0.3, 0.5, 0.2], "nodes": 100000, "time": 950,
+//      "cancelled": false, "pv": ["e2e4", ...],
+//      "multipv": [{"move": "e2e4", "score": {"cp": 30}, "pv": [...]}, ...],
+//      "root_moves": [{"move": "e2e4", "visits": 60000, "q": 0.55,
+//                      "policy": 0.4, "state": "O", "score": {"cp": 30}}, ...]}
+//
+// `POST /cancel` with `{"id": "a"}` drops the request if it is queued, or
+// stops its search early if it is running, and `GET /status` lists the
+// running and queued requests. Requests without an id are given one
+// starting with `#`, which is reserved for them. A request is cancelled
+// in the same way if its client hangs up before the reply. Errors are
+// replied as `{"error": "..."}`.
+
+const MAX_HEADER: usize = 16 << 10;
+const MAX_BODY: usize = 1 << 20;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const CANCEL_POLL: Duration = Duration::from_millis(5);
+const HANGUP_POLL: Duration = Duration::from_millis(100);
+
+/// Serves analysis on `addr` until the process is killed, searching
+/// every request in turn with a single engine.
+pub fn run(
+    addr: &str,
+    policy: &'static PolicyNetwork,
+    value: &'static ValueNetwork,
+    threads: usize,
+    hash_mb: usize,
+) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    let queue = Arc::new(Queue::default());
+
+    let mut engine = Engine::new(policy, value, |_| {});
+    engine.set_option("Threads", Some(&threads.to_string()));
+    engine.set_option("Hash", Some(&hash_mb.to_string()));
+
+    let engine_queue = queue.clone();
+    thread::spawn(move || analyse_queued(engine, &engine_queue));
+
+    println!("serving analysis on {}", listener.local_addr()?);
+
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+
+        let queue = queue.clone();
+
+        thread::spawn(move || {
+            if let Err(err) = handle_connection(stream, &queue) {
+                println!("connection failed: {err}");
+            }
+        });
+    }
+
+    Ok(())
+}
+
+struct Request {
+    fen: String,
+    moves: Vec<String>,
+    options: GoOptions,
+    /// Number of root moves to list, which does not change the search.
+    multipv: usize,
+    contempt: i32,
+}
+
+struct Job {
+    id: String,
+    request: Request,
+    cancelled: Arc<AtomicBool>,
+    reply: mpsc::Sender<Reply>,
+}
+
+type Reply = (u16, Json);
+
+#[derive(Default)]
+struct Queue {
+    state: Mutex<QueueState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    pending: VecDeque<Job>,
+    running: Option<(String, Arc<AtomicBool>)>,
+    next_id: u64,
+}
+
+impl Queue {
+    /// Queues a request, or replies straight away if its id is taken,
+    /// returning the id it is queued under.
+    fn push(&self, id: Option<String>, request: Request) -> (String, mpsc::Receiver<Reply>) {
+        let (reply, receiver) = mpsc::channel();
+        let mut state = self.state.lock().unwrap();
+
+        let id = id.unwrap_or_else(|| {
+            state.next_id += 1;
+            format!("#{}", state.next_id)
+        });
+
+        let taken = state
+            .running
+            .as_ref()
+            .is_some_and(|(running, _)| *running == id)
+            || state.pending.iter().any(|job| job.id == id);
+
+        if taken {
+            let _ = reply.send(error(409, &format!("request {id} already queued")));
+        } else {
+            state.pending.push_back(Job {
+                id: id.clone(),
+                request,
+                cancelled: Arc::new(AtomicBool::new(false)),
+                reply,
+            });
+
+            self.ready.notify_one();
+        }
+
+        (id, receiver)
+    }
+
+    /// Waits for the next request, marking it as running.
+    fn pop(&self) -> Job {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if let Some(job) = state.pending.pop_front() {
+                state.running = Some((job.id.clone(), job.cancelled.clone()));
+                return job;
+            }
+
+            state = self.ready.wait(state).unwrap();
+        }
+    }
+
+    fn finish(&self) {
+        self.state.lock().unwrap().running = None;
+    }
+
+    /// Cancels a request, returning whether it was found.
+    fn cancel(&self, id: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+
+        if let Some((_, cancelled)) = state.running.as_ref().filter(|(running, _)| running == id) {
+            cancelled.store(true, Ordering::Relaxed);
+            return true;
+        }
+
+        if let Some(idx) = state.pending.iter().position(|job| job.id == id) {
+            let job = state.pending.remove(idx).unwrap();
+            let _ = job
+                .reply
+                .send(error(409, &format!("request {id} cancelled")));
+            return true;
+        }
+
+        false
+    }
+
+    fn status(&self) -> Json {
+        let state = self.state.lock().unwrap();
+
+        Json::object([
+            (
+                "running",
+                state.running.as_ref().map(|(id, _)| id.as_str()).into(),
+            ),
+            (
+                "queued",
+                state
+                    .pending
+                    .iter()
+                    .map(|job| job.id.as_str())
+                    .collect::<Vec<_>>()
+                    .into(),
+            ),
+        ])
+    }
+}
+
+fn analyse_queued(mut engine: Engine, queue: &Queue) {
+    let mut contempt = None;
+
+    loop {
+        let job = queue.pop();
+        let request = &job.request;
+
+        // values in the tree were backed up with the old contempt
+        if contempt != Some(request.contempt) {
+            engine.new_game();
+            engine.set_option("Contempt", Some(&request.contempt.to_string()));
+            contempt = Some(request.contempt);
+        }
+
+        let moves = request.moves.iter().map(String::as_str).collect::<Vec<_>>();
+        engine.set_position(&request.fen, &moves);
+
+        let timer = Instant::now();
+        engine.start_search(request.options);
+
+        while engine.is_searching() {
+            if job.cancelled.load(Ordering::Relaxed) {
+                engine.stop();
+                break;
+            }
+
+            thread::sleep(CANCEL_POLL);
+        }
+
+        let time = timer.elapsed().as_millis();
+        let cancelled = job.cancelled.load(Ordering::Relaxed);
+        let result = engine.wait().unwrap();
+        let reply = result_to_json(&job.id, result, request.multipv, time, cancelled);
+
+        queue.finish();
+        let _ = job.reply.send((200, reply));
+    }
+}
+
+fn result_to_json(
+    id: &str,
+    result: &SearchResult,
+    multipv: usize,
+    time: u128,
+    cancelled: bool,
+) -> Json {
+    let multipv = result
+        .root_moves
+        .iter()
+        .take(multipv)
+        .map(|root_move| {
+            Json::object([
+                ("move", root_move.mov.as_str().into()),
+                ("score", root_move.score.to_json()),
+                ("pv", root_move.pv.clone().into()),
+            ])
+        })
+        .collect::<Vec<_>>();
+
+    let root_moves = result
+        .root_moves
+        .iter()
+        .map(|root_move| {
+            Json::object([
+                ("move", root_move.mov.as_str().into()),
+                ("visits", root_move.visits.into()),
+                ("q", root_move.q.into()),
+                ("policy", root_move.policy.into()),
+                ("state", root_move.state.to_string().into()),
+                ("score", root_move.score.to_json()),
+            ])
+        })
+        .collect::<Vec<_>>();
+
+    Json::object([
+        ("id", id.into()),
+        ("bestmove", result.best_move.as_str().into()),
+        ("score", result.score.to_json()),
+        (
+            "wdl",
+            vec![result.wdl.win, result.wdl.draw, result.wdl.loss].into(),
+        ),
+        ("nodes", result.nodes.into()),
+        ("time", time.into()),
+        ("cancelled", cancelled.into()),
+        ("pv", result.pv.clone().into()),
+        ("multipv", Json::Array(multipv)),
+        ("root_moves", Json::Array(root_moves)),
+    ])
+}
+
+fn error(status: u16, message: &str) -> Reply {
+    (status, Json::object([("error", message.into())]))
+}
+
+fn parse_request(body: &Json) -> Result<(Option<String>, Request), String> {
+    let field = |name| body.get(name);
+
+    let count = |name| -> Result<Option<u64>, String> {
+        field(name)
+            .map(|value| {
+                value
+                    .as_u64()
+                    .ok_or_else(|| format!("{name} must be a non-negative integer"))
+            })
+            .transpose()
+    };
+
+    let id = match field("id") {
+        None => None,
+        Some(Json::String(id)) if id.starts_with('#') => {
+            return Err("ids starting with # are reserved".to_string())
+        }
+        Some(Json::String(id)) => Some(id.clone()),
+        Some(Json::Number(_)) => Some(count("id")?.ok_or("id must be an integer")?.to_string()),
+        Some(_) => return Err("id must be a string or integer".to_string()),
+    };
+
+    let fen = match field("fen") {
+        None => ChessState::STARTPOS.to_string(),
+        Some(fen) => {
+            let fen = fen.as_str().ok_or("fen must be a string")?;
+
+            if !ChessState::is_valid_fen(fen) {
+                return Err(format!("invalid fen: {fen}"));
+            }
+
+            fen.to_string()
+        }
+    };
+
+    let mut moves = Vec::new();
+
+    if let Some(list) = field("moves") {
+        let list = list.as_array().ok_or("moves must be an array")?;
+        let mut pos = ChessState::from_fen(&fen);
+
+        for mov in list {
+            let mov = mov.as_str().ok_or("moves must be strings")?;
+
+            if !pos.is_legal_uci_move(mov) {
+                return Err(format!("illegal move: {mov}"));
+            }
+
+            pos.make_uci_move(mov);
+            moves.push(mov.to_string());
+        }
+    }
+
+    let options = GoOptions {
+        nodes: count("nodes")?.map(|nodes| nodes as usize),
+        movetime: count("movetime")?.map(u128::from),
+        depth: count("depth")?.map(|depth| depth as usize),
+        ..GoOptions::default()
+    };
+
+    if options.nodes.is_none() && options.movetime.is_none() && options.depth.is_none() {
+        return Err("one of nodes, movetime or depth is required".to_string());
+    }
+
+    let multipv = count("multipv")?.unwrap_or(1).max(1) as usize;
+
+    let contempt = match field("contempt") {
+        None => 0,
+        Some(contempt) => contempt
+            .as_f64()
+            .filter(|c| c.fract() == 0.0 && c.abs() <= 1000.0)
+            .ok_or("contempt must be an integer from -1000 to 1000")?
+            as i32,
+    };
+
+    Ok((
+        id,
+        Request {
+            fen,
+            moves,
+            options,
+            multipv,
+            contempt,
+        },
+    ))
+}
+
+fn handle_connection(stream: TcpStream, queue: &Queue) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    let (status, body) = match read_head(&mut reader)? {
+        None => error(431, "request header too large"),
+        Some((_, _, content_length)) if content_length > MAX_BODY => {
+            error(413, "request body too large")
+        }
+        Some((method, path, content_length)) => {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+
+            let body = String::from_utf8(body).map_err(|_| "body is not utf-8".to_string());
+            respond(&method, &path, body, queue, || connected(&stream))
+        }
+    };
+
+    write_response(stream, status, &body)
+}
+
+/// Method, path and content length of a request, or `None` if its
+/// request line and headers are longer than [`MAX_HEADER`].
+fn read_head(reader: &mut impl BufRead) -> io::Result<Option<(String, String, usize)>> {
+    let mut remaining = MAX_HEADER as u64;
+    let mut read_line = |line: &mut String| -> io::Result<bool> {
+        remaining -= (&mut *reader).take(remaining).read_line(line)? as u64;
+        Ok(remaining > 0)
+    };
+
+    let mut line = String::new();
+
+    if !read_line(&mut line)? {
+        return Ok(None);
+    }
+
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut content_length = 0;
+
+    loop {
+        let mut header = String::new();
+
+        if !read_line(&mut header)? {
+            return Ok(None);
+        }
+
+        if header.trim().is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(usize::MAX);
+            }
+        }
+    }
+
+    Ok(Some((method, path, content_length)))
+}
+
+/// Whether the client is still waiting for its reply. It sends nothing
+/// after its request, so a read that would not block means it hung up.
+fn connected(stream: &TcpStream) -> bool {
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+
+    let open = match stream.peek(&mut [0]) {
+        Ok(read) => read > 0,
+        Err(err) => err.kind() == io::ErrorKind::WouldBlock,
+    };
+
+    stream.set_nonblocking(false).is_ok() && open
+}
+
+fn respond(
+    method: &str,
+    path: &str,
+    body: Result<String, String>,
+    queue: &Queue,
+    connected: impl Fn() -> bool,
+) -> Reply {
+    let json = || {
+        let body = body.clone()?;
+
+        if body.trim().is_empty() {
+            Ok(Json::Object(Vec::new()))
+        } else {
+            Json::parse(&body).map_err(|err| format!("invalid json: {err}"))
+        }
+    };
+
+    match (method, path) {
+        ("POST", "/analyse") => {
+            let (id, request) = match json().and_then(|json| parse_request(&json)) {
+                Ok(parsed) => parsed,
+                Err(err) => return error(400, &err),
+            };
+
+            let (id, reply) = queue.push(id, request);
+
+            loop {
+                match reply.recv_timeout(HANGUP_POLL) {
+                    Ok(reply) => return reply,
+                    Err(RecvTimeoutError::Timeout) if connected() => {}
+                    Err(RecvTimeoutError::Timeout) => {
+                        queue.cancel(&id);
+                        return error(500, "client hung up");
+                    }
+                    Err(RecvTimeoutError::Disconnected) => return error(500, "search failed"),
+                }
+            }
+        }
+        ("POST", "/cancel") => {
+            let json = match json() {
+                Ok(json) => json,
+                Err(err) => return error(400, &err),
+            };
+
+            let id = match json.get("id") {
+                Some(Json::String(id)) => id.clone(),
+                Some(Json::Number(id)) => id.to_string(),
+                _ => return error(400, "id is required"),
+            };
+
+            if queue.cancel(&id) {
+                (200, Json::object([("cancelled", true.into())]))
+            } else {
+                error(404, &format!("no request {id}"))
+            }
+        }
+        ("GET", "/status") => (200, queue.status()),
+        (_, "/analyse" | "/cancel" | "/status") => error(405, "method not allowed"),
+        _ => error(404, "not found"),
+    }
+}
+
+fn write_response(mut stream: TcpStream, status: u16, body: &Json) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    };
+
+    let body = body.to_string();
+
+    write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\r\n\
+         {body}",
+        body.len()
+    )?;
+
+    stream.flush()
+}
@@ -0,0 +1,135 @@
+mod generate;
+mod index;
+
+use std::{collections::HashMap, fs::File, io, path::Path};
+
+use memmap2::Mmap;
+use montyformat::chess::{GameState, Position};
+
+use index::{classify, Material};
+
+/// Largest number of men supported by the generator.
+pub const MAX_MEN: usize = 4;
+
+/// Win/draw/loss and distance to mate tables for endings with few men,
+/// generated locally by [`Tablebases::generate`].
+///
+/// Each entry is a single byte from the side to move's perspective,
+/// `0` for a draw, `1..=127` for a win and `128 + n` for a loss, where the
+/// distance to mate is in plies. Positions with an en passant square are
+/// not stored, but en passant replies are considered when solving.
+#[derive(Default)]
+pub struct Tablebases {
+    tables: HashMap<Material, Table>,
+    max_pieces: usize,
+}
+
+struct Table {
+    data: Mmap,
+}
+
+impl Table {
+    fn open(path: &Path, material: &Material) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let data = unsafe { Mmap::map(&file)? };
+
+        if data.len() != material.size() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} has the wrong size", path.display()),
+            ));
+        }
+
+        Ok(Self { data })
+    }
+}
+
+impl Tablebases {
+    /// Opens every table in `dir`, missing tables are skipped.
+    pub fn load(dir: &str) -> io::Result<Self> {
+        let mut tbs = Self::default();
+
+        for material in Material::all(MAX_MEN) {
+            let path = Path::new(dir).join(file_name(&material));
+
+            if path.exists() {
+                let table = Table::open(&path, &material)?;
+                tbs.insert(material, table);
+            }
+        }
+
+        Ok(tbs)
+    }
+
+    /// Generates all tables with up to `max_men` men into `dir`, skipping
+    /// any that already exist.
+    pub fn generate(dir: &str, max_men: usize, threads: usize) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+
+        let mut tbs = Self::default();
+
+        for material in Material::all(max_men.min(MAX_MEN)) {
+            let path = Path::new(dir).join(file_name(&material));
+
+            if !path.exists() {
+                generate::table(&tbs, &material, &path, threads.max(1))?;
+            }
+
+            let table = Table::open(&path, &material)?;
+            tbs.insert(material, table);
+        }
+
+        Ok(tbs)
+    }
+
+    fn insert(&mut self, material: Material, table: Table) {
+        self.max_pieces = self.max_pieces.max(material.men() - 2);
+        self.tables.insert(material, table);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tables.len()
+    }
+
+    /// Result of the position with perfect play, if it is covered by a
+    /// loaded table and not affected by the fifty-move rule.
+    pub fn probe(&self, pos: &Position) -> Option<GameState> {
+        if pos.rights() != 0 || pos.enp_sq() != 0 {
+            return None;
+        }
+
+        let state = match self.probe_raw(pos)? {
+            0 => GameState::Draw,
+            v @ 1..=127 => GameState::Won(v),
+            v => GameState::Lost(v - 128),
+        };
+
+        match state {
+            GameState::Won(n) | GameState::Lost(n)
+                if usize::from(pos.halfm()) + usize::from(n) > 100 =>
+            {
+                None
+            }
+            _ => Some(state),
+        }
+    }
+
+    fn probe_raw(&self, pos: &Position) -> Option<u8> {
+        if pos.occ().count_ones() == 2 {
+            return Some(0);
+        }
+
+        let (material, stm, squares) = classify(pos, self.max_pieces)?;
+        let table = self.tables.get(&material)?;
+
+        Some(table.data[material.encode(stm, &squares)])
+    }
+}
+
+fn file_name(material: &Material) -> String {
+    format!("{}.mtb", material.name())
+}
@@ -0,0 +1,264 @@
+use std::{
+    fs, io,
+    ops::Range,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use montyformat::chess::{Castling, Piece, Position};
+
+use super::{
+    index::{classify, Material},
+    Tablebases,
+};
+
+const RANK_1_OR_8: u64 = 0xFF00_0000_0000_00FF;
+
+/// Retrograde analysis by repeated forward passes over the table. On pass
+/// `n` exactly the positions with a distance to mate of `n` plies are
+/// resolved, so that distances are minimal for the winner and maximal for
+/// the loser. Tables reachable by a capture or promotion must be in `tbs`,
+/// otherwise the first one found missing is reported as an error.
+pub fn table(tbs: &Tablebases, material: &Material, path: &Path, threads: usize) -> io::Result<()> {
+    let timer = Instant::now();
+    let size = material.size();
+    let castling = Castling::default();
+
+    let values = (0..size).map(|_| AtomicU8::new(0)).collect::<Vec<_>>();
+    let legal = (0..size)
+        .map(|_| AtomicBool::new(false))
+        .collect::<Vec<_>>();
+
+    // mark legal positions and checkmates
+    parallel(size, threads, |range| {
+        for index in range {
+            let (stm, squares) = material.decode(index);
+            let pos = material.position(stm, &squares);
+
+            if !is_legal(&pos, material.men()) {
+                continue;
+            }
+
+            legal[index].store(true, Ordering::Relaxed);
+
+            let mut moves = 0;
+            pos.map_legal_moves(&castling, |_| moves += 1);
+
+            if moves == 0 && pos.in_check() {
+                values[index].store(128, Ordering::Relaxed);
+            }
+        }
+    });
+
+    // longest distance to mate reachable by a capture or promotion, every
+    // such child is seen on the first pass, or through an en passant reply
+    let foreign_dtm = AtomicU8::new(0);
+    let missing = Mutex::new(None);
+    let mut pass = 1;
+
+    loop {
+        let changed = AtomicBool::new(false);
+
+        parallel(size, threads, |range| {
+            for index in range {
+                if !legal[index].load(Ordering::Relaxed)
+                    || values[index].load(Ordering::Relaxed) != 0
+                {
+                    continue;
+                }
+
+                match resolve(tbs, material, &values, &foreign_dtm, &castling, index, pass) {
+                    Ok(Some(value)) => {
+                        values[index].store(value, Ordering::Relaxed);
+                        changed.store(true, Ordering::Relaxed);
+                    }
+                    Ok(None) => {}
+                    Err(table) => *missing.lock().unwrap() = Some(table),
+                }
+            }
+        });
+
+        if let Some(table) = missing.lock().unwrap().take() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} requires the {} table", material.name(), table.name()),
+            ));
+        }
+
+        if !changed.load(Ordering::Relaxed) && pass > foreign_dtm.load(Ordering::Relaxed) {
+            break;
+        }
+
+        pass += 1;
+
+        assert!(pass < 128, "{}: distance to mate too long", material.name());
+    }
+
+    let data = values
+        .iter()
+        .map(|value| value.load(Ordering::Relaxed))
+        .collect::<Vec<_>>();
+
+    let mut counts = [0usize; 3];
+    for (value, legal) in data.iter().zip(legal.iter()) {
+        if legal.load(Ordering::Relaxed) {
+            counts[usize::from(*value > 0) + usize::from(*value >= 128)] += 1;
+        }
+    }
+
+    // write to a temporary file first so that interrupted
+    // generation never leaves a truncated table behind
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, &data)?;
+    fs::rename(&temp_path, path)?;
+
+    println!(
+        "{}: {} won, {} drawn, {} lost, {:.1}s",
+        material.name(),
+        counts[1],
+        counts[0],
+        counts[2],
+        timer.elapsed().as_secs_f32(),
+    );
+
+    Ok(())
+}
+
+fn is_legal(pos: &Position, men: usize) -> bool {
+    let occ = pos.occ();
+    let them = pos.stm() ^ 1;
+
+    occ.count_ones() as usize == men
+        && pos.piece(Piece::PAWN) & RANK_1_OR_8 == 0
+        && !pos.is_square_attacked(pos.king_sq(them), them, occ)
+}
+
+fn resolve(
+    tbs: &Tablebases,
+    material: &Material,
+    values: &[AtomicU8],
+    foreign_dtm: &AtomicU8,
+    castling: &Castling,
+    index: usize,
+    pass: u8,
+) -> Result<Option<u8>, Material> {
+    let (stm, squares) = material.decode(index);
+    let pos = material.position(stm, &squares);
+    let children = Children::new(tbs, material, values, foreign_dtm, castling, &pos)?;
+
+    Ok(if children.best_win <= pass {
+        Some(children.best_win)
+    } else if children.moves > 0 && children.all_won && children.longest_loss <= pass {
+        Some(128 + children.longest_loss)
+    } else {
+        None
+    })
+}
+
+/// Values of the children of a position, seen from its side to move.
+struct Children {
+    moves: usize,
+    all_won: bool,
+    best_win: u8,
+    longest_loss: u8,
+}
+
+impl Children {
+    fn new(
+        tbs: &Tablebases,
+        material: &Material,
+        values: &[AtomicU8],
+        foreign_dtm: &AtomicU8,
+        castling: &Castling,
+        pos: &Position,
+    ) -> Result<Self, Material> {
+        let mut children = Self {
+            moves: 0,
+            all_won: true,
+            best_win: u8::MAX,
+            longest_loss: 0,
+        };
+
+        let mut missing = None;
+
+        pos.map_legal_moves(castling, |mov| {
+            let mut child = *pos;
+            child.make(mov, castling);
+
+            children.moves += 1;
+
+            // child values are from the opponent's perspective
+            match lookup(tbs, material, values, foreign_dtm, castling, &child) {
+                Ok(0) => children.all_won = false,
+                Ok(v @ 1..=127) => children.longest_loss = children.longest_loss.max(v + 1),
+                Ok(v) => {
+                    children.best_win = children.best_win.min(v - 127);
+                    children.all_won = false;
+                }
+                Err(table) => missing = Some(table),
+            }
+        });
+
+        missing.map_or(Ok(children), Err)
+    }
+}
+
+fn lookup(
+    tbs: &Tablebases,
+    material: &Material,
+    values: &[AtomicU8],
+    foreign_dtm: &AtomicU8,
+    castling: &Castling,
+    pos: &Position,
+) -> Result<u8, Material> {
+    // tables do not store en passant rights, so a position where an en
+    // passant capture is legal is solved by looking one ply further
+    if pos.enp_sq() != 0 && has_en_passant(pos, castling) {
+        let children = Children::new(tbs, material, values, foreign_dtm, castling, pos)?;
+
+        let value = if children.best_win != u8::MAX {
+            children.best_win
+        } else if children.all_won {
+            128 + children.longest_loss
+        } else {
+            0
+        };
+
+        // the value may only be known on a later pass than its distance
+        foreign_dtm.fetch_max(value & 127, Ordering::Relaxed);
+        return Ok(value);
+    }
+
+    let (child, stm, squares) =
+        classify(pos, material.men() - 2).expect("moves never add men to the board");
+
+    if child == *material {
+        return Ok(values[material.encode(stm, &squares)].load(Ordering::Relaxed));
+    }
+
+    let value = tbs.probe_raw(pos).ok_or(child)?;
+    foreign_dtm.fetch_max(value & 127, Ordering::Relaxed);
+    Ok(value)
+}
+
+fn has_en_passant(pos: &Position, castling: &Castling) -> bool {
+    let mut found = false;
+    pos.map_legal_moves(castling, |mov| found |= mov.is_en_passant());
+    found
+}
+
+fn parallel<F: Fn(Range<usize>) + Sync>(size: usize, threads: usize, f: F) {
+    let chunk = size.div_ceil(threads);
+
+    thread::scope(|s| {
+        for start in (0..size).step_by(chunk) {
+            let f = &f;
+            s.spawn(move || f(start..size.min(start + chunk)));
+        }
+    });
+}
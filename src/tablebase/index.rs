@@ -0,0 +1,257 @@
+use montyformat::chess::{Piece, Position, Side};
+
+use super::MAX_MEN;
+
+/// Non-king material of each side, strongest piece first and padded with
+/// [`Piece::EMPTY`]. The stronger side of a position is always stored as white.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Material {
+    strong: [usize; MAX_PIECES],
+    weak: [usize; MAX_PIECES],
+}
+
+/// Squares of the strong king, the weak king and then each piece in table order.
+pub type Squares = [usize; MAX_MEN];
+
+const MAX_PIECES: usize = MAX_MEN - 2;
+
+impl Material {
+    pub fn new(strong: &[usize], weak: &[usize]) -> Self {
+        let pad = |pieces: &[usize]| {
+            let mut padded = [Piece::EMPTY; MAX_PIECES];
+            padded[..pieces.len()].copy_from_slice(pieces);
+            padded.sort_by(|a, b| b.cmp(a));
+            padded
+        };
+
+        let (mut strong, mut weak) = (pad(strong), pad(weak));
+
+        if stronger(&weak, &strong) {
+            std::mem::swap(&mut strong, &mut weak);
+        }
+
+        Self { strong, weak }
+    }
+
+    /// Every table with at least one piece besides the kings and at
+    /// most `max_men` men, in an order where all tables reachable by
+    /// a capture or promotion come first.
+    pub fn all(max_men: usize) -> Vec<Self> {
+        const PIECES: [usize; 5] = [
+            Piece::PAWN,
+            Piece::KNIGHT,
+            Piece::BISHOP,
+            Piece::ROOK,
+            Piece::QUEEN,
+        ];
+
+        let mut all = Vec::new();
+
+        if max_men >= 3 {
+            for &a in &PIECES {
+                all.push(Self::new(&[a], &[]));
+            }
+        }
+
+        if max_men >= 4 {
+            for (i, &a) in PIECES.iter().enumerate() {
+                for &b in &PIECES[..=i] {
+                    all.push(Self::new(&[a, b], &[]));
+                    all.push(Self::new(&[a], &[b]));
+                }
+            }
+        }
+
+        all.sort_by_key(|material| (material.men(), material.pawns()));
+        all
+    }
+
+    pub fn men(&self) -> usize {
+        2 + self.pieces().count()
+    }
+
+    pub fn pawns(&self) -> usize {
+        self.pieces().filter(|&pc| pc == Piece::PAWN).count()
+    }
+
+    /// Non-king pieces in table order.
+    pub fn pieces(&self) -> impl Iterator<Item = usize> + '_ {
+        self.strong_pieces().chain(self.weak_pieces())
+    }
+
+    fn strong_pieces(&self) -> impl Iterator<Item = usize> + '_ {
+        self.strong.iter().copied().filter(|&pc| pc != Piece::EMPTY)
+    }
+
+    fn weak_pieces(&self) -> impl Iterator<Item = usize> + '_ {
+        self.weak.iter().copied().filter(|&pc| pc != Piece::EMPTY)
+    }
+
+    pub fn name(&self) -> String {
+        const CHARS: [char; 8] = [' ', ' ', 'P', 'N', 'B', 'R', 'Q', 'K'];
+
+        let strong = self.strong_pieces().map(|pc| CHARS[pc]).collect::<String>();
+        let weak = self.weak_pieces().map(|pc| CHARS[pc]).collect::<String>();
+
+        format!("K{strong}vK{weak}")
+    }
+
+    fn king_squares(&self) -> &'static [u8] {
+        if self.pawns() > 0 {
+            &PAWN_KINGS
+        } else {
+            &PAWNLESS_KINGS
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        2 * self.king_squares().len() * 64usize.pow(self.men() as u32 - 1)
+    }
+
+    /// Index of the position with side to move `stm`.
+    pub fn encode(&self, stm: usize, squares: &Squares) -> usize {
+        let king = squares[0];
+        let mut flip = 0;
+
+        if king & 7 > 3 {
+            flip ^= 7;
+        }
+
+        let pawnless = self.pawns() == 0;
+
+        if pawnless && king >> 3 > 3 {
+            flip ^= 56;
+        }
+
+        let king = king ^ flip;
+        let diagonal = pawnless && king >> 3 > king & 7;
+
+        let transform = |sq: usize| {
+            let sq = sq ^ flip;
+
+            if diagonal {
+                ((sq & 7) << 3) | (sq >> 3)
+            } else {
+                sq
+            }
+        };
+
+        let king_idx = self
+            .king_squares()
+            .iter()
+            .position(|&sq| usize::from(sq) == transform(squares[0]))
+            .unwrap();
+
+        let mut index = stm * self.king_squares().len() + king_idx;
+
+        for &sq in &squares[1..self.men()] {
+            index = index * 64 + transform(sq);
+        }
+
+        index
+    }
+
+    /// Inverse of [`Material::encode`].
+    pub fn decode(&self, mut index: usize) -> (usize, Squares) {
+        let mut squares = [0; MAX_MEN];
+
+        for sq in squares[1..self.men()].iter_mut().rev() {
+            *sq = index % 64;
+            index /= 64;
+        }
+
+        let kings = self.king_squares();
+        squares[0] = usize::from(kings[index % kings.len()]);
+
+        (index / kings.len(), squares)
+    }
+
+    /// Builds the position, which may be illegal.
+    pub fn position(&self, stm: usize, squares: &Squares) -> Position {
+        let mut bbs = [0; 8];
+
+        let sides = [Side::WHITE, Side::BLACK]
+            .into_iter()
+            .chain(self.strong_pieces().map(|_| Side::WHITE))
+            .chain(self.weak_pieces().map(|_| Side::BLACK));
+        let pieces = [Piece::KING, Piece::KING].into_iter().chain(self.pieces());
+
+        for ((side, piece), &sq) in sides.zip(pieces).zip(squares) {
+            bbs[side] |= 1 << sq;
+            bbs[piece] |= 1 << sq;
+        }
+
+        #[allow(deprecated)]
+        Position::from_raw(bbs, stm == Side::BLACK, 0, 0, 0, 1)
+    }
+}
+
+/// Material, side to move and table-order squares of a position, with
+/// the colours swapped if black is the stronger side.
+pub fn classify(pos: &Position, max_pieces: usize) -> Option<(Material, usize, Squares)> {
+    let bbs = pos.bbs();
+
+    if (bbs[Side::WHITE] | bbs[Side::BLACK]).count_ones() as usize > max_pieces + 2 {
+        return None;
+    }
+
+    let mut sides = [Side::WHITE, Side::BLACK].map(|side| {
+        let mut pieces = [Piece::EMPTY; MAX_PIECES];
+        let mut squares = [0; MAX_PIECES];
+        let mut count = 0;
+
+        for piece in (Piece::PAWN..=Piece::QUEEN).rev() {
+            let mut bb = bbs[piece] & bbs[side];
+
+            while bb > 0 {
+                pieces[count] = piece;
+                squares[count] = bb.trailing_zeros() as usize;
+                count += 1;
+                bb &= bb - 1;
+            }
+        }
+
+        (pieces, squares, count, pos.king_sq(side))
+    });
+
+    let mut stm = pos.stm();
+
+    if stronger(&sides[1].0, &sides[0].0) {
+        sides.swap(0, 1);
+        stm ^= 1;
+
+        for (_, squares, _, king) in sides.iter_mut() {
+            *king ^= 56;
+
+            for sq in squares.iter_mut() {
+                *sq ^= 56;
+            }
+        }
+    }
+
+    let [(strong, strong_sqs, strong_count, strong_king), (weak, weak_sqs, weak_count, weak_king)] =
+        sides;
+
+    let mut squares = [0; MAX_MEN];
+    squares[0] = strong_king;
+    squares[1] = weak_king;
+    squares[2..2 + strong_count].copy_from_slice(&strong_sqs[..strong_count]);
+    squares[2 + strong_count..2 + strong_count + weak_count]
+        .copy_from_slice(&weak_sqs[..weak_count]);
+
+    Some((Material { strong, weak }, stm, squares))
+}
+
+fn stronger(a: &[usize; MAX_PIECES], b: &[usize; MAX_PIECES]) -> bool {
+    let count =
+        |pieces: &[usize; MAX_PIECES]| pieces.iter().filter(|&&pc| pc != Piece::EMPTY).count();
+
+    (count(a), a) > (count(b), b)
+}
+
+const PAWN_KINGS: [u8; 32] = [
+    0, 1, 2, 3, 8, 9, 10, 11, 16, 17, 18, 19, 24, 25, 26, 27, 32, 33, 34, 35, 40, 41, 42, 43, 48,
+    49, 50, 51, 56, 57, 58, 59,
+];
+
+const PAWNLESS_KINGS: [u8; 10] = [0, 1, 2, 3, 9, 10, 11, 18, 19, 27];
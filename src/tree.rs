@@ -1,10 +1,13 @@
 mod half;
 mod hash;
+mod history;
 mod lock;
 mod node;
 
 use half::TreeHalf;
 use hash::{HashEntry, HashTable};
+use history::History;
+pub use history::HistoryKey;
 use node::NodeStatsDelta;
 pub use node::{Node, NodePtr};
 
@@ -256,22 +259,32 @@ impl ButterflyTable {
     }
 
     fn update(&self, side: usize, mov: Move, score: f32, params: &MctsParams) {
-        if !score.is_finite() {
-            return;
-        }
+        update_history_entry(
+            self.entry(side, mov),
+            score,
+            1.0,
+            params.butterfly_reduction_factor(),
+        );
+    }
+}
+
+/// Moves a history entry towards the centipawn equivalent of `score`,
+/// scaled by `weight`.
+fn update_history_entry(cell: &AtomicI16, score: f32, weight: f32, reduction_factor: i32) {
+    if !score.is_finite() {
+        return;
+    }
 
-        let score = score.clamp(0.001, 0.999);
-        let cp = (-400.0 * ((1.0 / score) - 1.0).ln()).round() as i32;
-        let cell = self.entry(side, mov);
+    let score = score.clamp(0.001, 0.999);
+    let cp = (-400.0 * weight * ((1.0 / score) - 1.0).ln()).round() as i32;
 
-        let mut current = cell.load(Ordering::Relaxed);
-        loop {
-            let delta = scale_bonus(current, cp, params.butterfly_reduction_factor());
-            let new = current.saturating_add(delta);
-            match cell.compare_exchange(current, new, Ordering::Relaxed, Ordering::Relaxed) {
-                Ok(_) => break,
-                Err(actual) => current = actual,
-            }
+    let mut current = cell.load(Ordering::Relaxed);
+    loop {
+        let delta = scale_bonus(current, cp, reduction_factor);
+        let new = current.saturating_add(delta);
+        match cell.compare_exchange(current, new, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => break,
+            Err(actual) => current = actual,
         }
     }
 }
@@ -290,6 +303,7 @@ pub struct Tree {
     half: AtomicBool,
     hash: HashTable,
     butterfly: ButterflyTable,
+    history: History,
     root_accumulator: RootAccumulator,
 }
 
@@ -325,6 +339,7 @@ impl Tree {
             half: AtomicBool::new(false),
             hash: HashTable::new(hash_cap / 4, threads),
             butterfly: ButterflyTable::new(),
+            history: History::new(),
             root_accumulator: RootAccumulator::new(threads),
         };
 
@@ -358,6 +373,12 @@ impl Tree {
         self.tree[self.half()].is_full()
     }
 
+    /// Permille of the current half of the tree in use.
+    pub fn hashfull(&self) -> usize {
+        let half = &self.tree[self.half()];
+        1000 * half.used().min(half.len()) / half.len().max(1)
+    }
+
     pub fn push_new_node(&self) -> Option<NodePtr> {
         self.tree[self.half()].reserve_nodes_thread(1, 0)
     }
@@ -474,6 +495,7 @@ impl Tree {
         self.clear_halves();
         self.hash.clear(threads);
         self.butterfly.clear();
+        self.history.clear();
         self.root_accumulator.reset(self.root_node());
     }
 
@@ -507,7 +529,11 @@ impl Tree {
         let stm = pos.stm();
 
         pos.map_moves_with_policies(policy, |mov, policy| {
-            let adjusted = policy + self.butterfly.policy_bonus(stm, mov, params);
+            let adjusted = policy
+                + self.butterfly.policy_bonus(stm, mov, params)
+                + self
+                    .history
+                    .policy_bonus(&HistoryKey::new(pos, mov), params);
             moves[count].write((mov, adjusted));
             count += 1;
             max = max.max(adjusted);
@@ -568,8 +594,11 @@ impl Tree {
         let stm = pos.stm();
         for action in 0..num_actions {
             let mov = self[actions_ptr + action].parent_move();
-            let policy =
-                pos.get_policy(mov, &hl, policy) + self.butterfly.policy_bonus(stm, mov, params);
+            let policy = pos.get_policy(mov, &hl, policy)
+                + self.butterfly.policy_bonus(stm, mov, params)
+                + self
+                    .history
+                    .policy_bonus(&HistoryKey::new(pos, mov), params);
 
             policies.push(policy);
             max = max.max(policy);
@@ -600,8 +629,13 @@ impl Tree {
         self.butterfly.update(side, mov, score, params);
     }
 
+    pub fn update_history(&self, key: &HistoryKey, score: f32, params: &MctsParams) {
+        self.history.update(key, score, params);
+    }
+
     pub fn clear_butterfly_table(&self) {
         self.butterfly.clear();
+        self.history.clear();
     }
 
     pub fn propogate_proven_mates(&self, ptr: NodePtr, child_state: GameState) {
@@ -609,27 +643,28 @@ impl Tree {
             // if the child node resulted in a loss, then
             // this node has a guaranteed win
             GameState::Lost(n) => self[ptr].set_state(GameState::Won(n + 1)),
-            // if the child node resulted in a win, then check if there are
-            // any non-won children, and if not, guaranteed loss for this node
-            GameState::Won(n) => {
+            // if the child node resulted in a win or a draw, then check if
+            // every child is won or drawn, and if so this node is a guaranteed
+            // draw if any child is drawn, as a draw beats a loss whatever the
+            // draw score, and a guaranteed loss otherwise
+            GameState::Won(_) | GameState::Draw => {
                 assert_ne!(self[ptr].num_actions(), 0);
 
-                let mut proven_loss = true;
-                let mut max_win_len = n;
+                let mut drawn = false;
+                let mut max_win_len = 0;
                 let first_child_ptr = self[ptr].actions();
 
                 for action in 0..self[ptr].num_actions() {
-                    let ptr = first_child_ptr + action;
-
-                    if let GameState::Won(n) = self[ptr].state() {
-                        max_win_len = n.max(max_win_len);
-                    } else {
-                        proven_loss = false;
-                        break;
+                    match self[first_child_ptr + action].state() {
+                        GameState::Won(n) => max_win_len = n.max(max_win_len),
+                        GameState::Draw => drawn = true,
+                        _ => return,
                     }
                 }
 
-                if proven_loss {
+                if drawn {
+                    self[ptr].set_state(GameState::Draw);
+                } else {
                     self[ptr].set_state(GameState::Lost(max_win_len + 1));
                 }
             }
@@ -638,7 +673,9 @@ impl Tree {
         }
     }
 
-    pub fn set_root_position(&mut self, new_root: &ChessState) {
+    /// Moves the root to `new_root`, keeping the subtree below it if it is
+    /// found within two plies of the old root, and returns whether it was.
+    pub fn set_root_position(&mut self, new_root: &ChessState) -> bool {
         let old_root = self.root.clone();
         self.root = new_root.clone();
 
@@ -646,13 +683,11 @@ impl Tree {
         self.reset_root_accumulator();
 
         if self.is_empty() {
-            return;
+            return false;
         }
 
         let mut found = false;
 
-        println!("info string searching for subtree");
-
         let root = self.recurse_find(self.root_node(), &old_root, new_root, 2);
 
         if !root.is_null() && self[root].has_children() {
@@ -662,14 +697,13 @@ impl Tree {
                 self[self.root_node()].clear();
                 self.copy_node_across(root, self.root_node(), false);
             }
-
-            println!("info string found subtree");
         }
 
         if !found {
-            println!("info string no subtree found");
             self.clear_halves();
         }
+
+        found
     }
 
     fn recurse_find(
@@ -711,6 +745,42 @@ impl Tree {
         NodePtr::NULL
     }
 
+    /// Hash of the structure and statistics of the tree below the root,
+    /// independent of where each node is stored.
+    pub fn checksum(&self) -> u64 {
+        const PRIME: u64 = 0x0000_0100_0000_01B3;
+
+        let mut hash = 0xCBF2_9CE4_8422_2325;
+        let mut mix = |val: u64| {
+            hash ^= val;
+            hash = hash.wrapping_mul(PRIME);
+        };
+
+        let mut stack = vec![self.root_node()];
+
+        while let Some(ptr) = stack.pop() {
+            let node = &self[ptr];
+
+            mix(u64::from(u16::from(node.parent_move())));
+            mix(u64::from(u16::from(node.state())));
+            mix(node.visits());
+            mix(u64::from(node.q().to_bits()));
+            mix(node.num_actions() as u64);
+
+            let first_child_ptr = node.actions();
+
+            if first_child_ptr.is_null() {
+                continue;
+            }
+
+            for action in (0..node.num_actions()).rev() {
+                stack.push(first_child_ptr + action);
+            }
+        }
+
+        hash
+    }
+
     pub fn get_best_child(&self, ptr: NodePtr) -> usize {
         self.get_best_child_by_key(ptr, |n| n.visits() as f32)
     }
@@ -743,11 +813,9 @@ impl Tree {
         best_child
     }
 
-    #[cfg(feature = "datagen")]
-    pub fn get_best_child_temp(&self, ptr: NodePtr, temp: f32) -> Move {
-        use rand::prelude::*;
-        use rand_distr::Uniform;
-
+    /// Samples a child in proportion to its visits raised to `1 / temp`,
+    /// where `rand` is a uniform sample in `[0, 1)`.
+    pub fn get_best_child_temp(&self, ptr: NodePtr, temp: f32, rand: f64) -> Move {
         let node = &self[ptr];
         let child_ptr = node.actions();
 
@@ -755,10 +823,6 @@ impl Tree {
             return self[child_ptr + self.get_best_child(ptr)].parent_move();
         }
 
-        let mut rng = rand::rng();
-        let dist = Uniform::new(0.0, 1.0).unwrap();
-        let rand = dist.sample(&mut rng);
-
         let mut total = 0.0;
         let mut distribution = vec![0.0; node.num_actions()];
         let t = 1.0 / f64::from(temp);
@@ -781,38 +845,4 @@ impl Tree {
 
         self[child_ptr + (node.num_actions() - 1)].parent_move()
     }
-
-    #[cfg(feature = "datagen")]
-    pub fn add_dirichlet_noise_to_node(&self, ptr: NodePtr, alpha: f32, prop: f32) {
-        use rand_distr::{Distribution, Gamma};
-
-        let node = &self[ptr];
-
-        if node.num_actions() <= 1 {
-            return;
-        }
-
-        let actions_ptr = node.actions();
-
-        let mut rng = rand::rng();
-        let k = node.num_actions();
-
-        // Symmetric Dirichlet via Gamma(alpha, 1) samples
-        let gamma = Gamma::<f32>::new(alpha, 1.0).unwrap();
-        let mut sum = 0.0;
-        let mut noise = Vec::with_capacity(k);
-        for _ in 0..k {
-            let x = gamma.sample(&mut rng);
-            sum += x;
-            noise.push(x);
-        }
-        // Guard against pathological underflow
-        let inv_sum = if sum > 0.0 { 1.0 / sum } else { 1.0 / k as f32 };
-
-        for (action, x) in noise.into_iter().enumerate() {
-            let child = &self[actions_ptr + action];
-            let mixed = (1.0 - prop) * child.policy() + prop * (x * inv_sum);
-            child.set_policy(mixed);
-        }
-    }
 }
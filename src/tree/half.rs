@@ -146,6 +146,10 @@ impl TreeHalf {
         self.used.load(Ordering::Relaxed)
     }
 
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
     pub fn is_full(&self) -> bool {
         self.used() >= self.nodes.len()
     }
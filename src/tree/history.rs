@@ -0,0 +1,137 @@
+use std::sync::atomic::{AtomicBool, AtomicI16, Ordering};
+
+use montyformat::chess::Piece;
+
+use crate::{
+    chess::{ChessState, Move, PieceTo},
+    mcts::MctsParams,
+};
+
+use super::update_history_entry;
+
+const NUM_PIECE_TO: usize = 2 * 8 * 64;
+
+// continuations only index real pieces, and the side of the
+// earlier move follows from the side of the current one
+const NUM_PIECES: usize = 6;
+const NUM_CONTINUATION_TO: usize = 2 * NUM_PIECES * 64;
+const NUM_CONTINUATIONS: usize = NUM_PIECES * 64 * NUM_CONTINUATION_TO;
+
+/// The context a move is played in, for indexing the histories.
+#[derive(Clone, Copy, Debug)]
+pub struct HistoryKey {
+    piece_to: usize,
+    continuation_to: usize,
+    recent: [Option<PieceTo>; 2],
+}
+
+impl HistoryKey {
+    pub fn new(pos: &ChessState, mov: Move) -> Self {
+        let piece = pos.board().get_pc(1 << mov.src());
+        let to = usize::from(mov.to());
+
+        Self {
+            piece_to: (pos.stm() * 8 + piece) * 64 + to,
+            continuation_to: (pos.stm() * NUM_PIECES + piece - Piece::PAWN) * 64 + to,
+            recent: pos.recent_moves(),
+        }
+    }
+
+    fn continuation(&self, ply: usize) -> Option<usize> {
+        let (piece, to) = self.recent[ply]?;
+        Some(((piece - Piece::PAWN) * 64 + to) * NUM_CONTINUATION_TO + self.continuation_to)
+    }
+}
+
+/// Piece-to history, and continuation histories keyed on the moves
+/// one and two plies before, complementing the butterfly table.
+pub struct History {
+    piece_to: Vec<AtomicI16>,
+    continuations: [Vec<AtomicI16>; 2],
+    written: AtomicBool,
+}
+
+impl History {
+    pub fn new() -> Self {
+        let table = |size| (0..size).map(|_| AtomicI16::new(0)).collect::<Vec<_>>();
+
+        Self {
+            piece_to: table(NUM_PIECE_TO),
+            continuations: [table(NUM_CONTINUATIONS), table(NUM_CONTINUATIONS)],
+            written: AtomicBool::new(false),
+        }
+    }
+
+    /// Zeroes the tables, unless nothing was written since the last clear.
+    pub fn clear(&self) {
+        if !self.written.swap(false, Ordering::Relaxed) {
+            return;
+        }
+
+        let tables = [
+            &self.piece_to,
+            &self.continuations[0],
+            &self.continuations[1],
+        ];
+
+        for entry in tables.into_iter().flatten() {
+            entry.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn policy_bonus(&self, key: &HistoryKey, params: &MctsParams) -> f32 {
+        let get = |entry: &AtomicI16, divisor: i32| {
+            f32::from(entry.load(Ordering::Relaxed)) / divisor.max(1) as f32
+        };
+
+        let mut bonus = get(
+            &self.piece_to[key.piece_to],
+            params.piece_to_policy_divisor(),
+        );
+
+        let divisors = [params.cont1_policy_divisor(), params.cont2_policy_divisor()];
+
+        for (ply, divisor) in divisors.into_iter().enumerate() {
+            if let Some(idx) = key.continuation(ply) {
+                bonus += get(&self.continuations[ply][idx], divisor);
+            }
+        }
+
+        bonus
+    }
+
+    pub fn update(&self, key: &HistoryKey, score: f32, params: &MctsParams) {
+        let piece_to_weight = params.piece_to_bonus_weight();
+
+        if piece_to_weight != 0.0 {
+            self.written.store(true, Ordering::Relaxed);
+            update_history_entry(
+                &self.piece_to[key.piece_to],
+                score,
+                piece_to_weight,
+                params.piece_to_reduction_factor(),
+            );
+        }
+
+        let settings = [
+            (params.cont1_bonus_weight(), params.cont1_reduction_factor()),
+            (params.cont2_bonus_weight(), params.cont2_reduction_factor()),
+        ];
+
+        for (ply, (weight, reduction_factor)) in settings.into_iter().enumerate() {
+            if weight == 0.0 {
+                continue;
+            }
+
+            if let Some(idx) = key.continuation(ply) {
+                self.written.store(true, Ordering::Relaxed);
+                update_history_entry(
+                    &self.continuations[ply][idx],
+                    score,
+                    weight,
+                    reduction_factor,
+                );
+            }
+        }
+    }
+}
@@ -276,7 +276,6 @@ impl Node {
         }
     }
 
-    #[cfg(feature = "datagen")]
     pub fn kld_gain(new_visit_dist: &[i32], old_visit_dist: &[i32]) -> Option<f64> {
         let new_parent_visits = new_visit_dist.iter().sum::<i32>();
         let old_parent_visits = old_visit_dist.iter().sum::<i32>();
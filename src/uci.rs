@@ -1,88 +1,47 @@
 use crate::{
-    chess::{ChessState, Move},
-    mcts::{Limits, MctsParams, SearchHelpers, Searcher, REPORT_ITERS},
+    chess::{ChessState, GameState},
+    distributed,
+    engine::{Engine, GoOptions},
+    epd,
+    mcts::{print_event, Limits, MctsParams, Searcher, Strength, MAX_ELO, MIN_ELO},
     networks::{PolicyNetwork, ValueNetwork},
+    serve,
+    tablebase::{Tablebases, MAX_MEN},
     tree::Tree,
+    xboard,
 };
 
-use std::{
-    io, process,
-    sync::atomic::{AtomicBool, Ordering},
-    time::Instant,
-};
+use std::{io, sync::atomic::AtomicBool, time::Instant};
 
-pub fn run(policy: &PolicyNetwork, value: &ValueNetwork, tcec_mode: bool) {
-    let mut pos = ChessState::default();
-    let mut root_game_ply = 0;
-    let mut params = MctsParams::default();
-    let mut hash_mb = 64;
-    let mut tree = Tree::new_mb(hash_mb, 1);
-    let mut report_moves = false;
-    let mut threads = 1;
-    let mut move_overhead = 400;
-    let mut uci_opponent_rating: Option<i32> = None;
-    let mut uci_rating_adv: Option<i32> = None;
-    let mut contempt_override: Option<i32> = None;
-    let mut contempt_analysis = false;
-
-    let mut stored_message: Option<String> = None;
+pub fn run(policy: &'static PolicyNetwork, value: &'static ValueNetwork, tcec_mode: bool) {
+    let mut engine = Engine::new(policy, value, print_event);
 
     loop {
-        let input = if let Some(msg) = stored_message {
-            msg.clone()
-        } else {
-            let mut input = String::new();
-            let bytes_read = io::stdin().read_line(&mut input).unwrap();
-
-            if bytes_read == 0 {
-                break;
-            }
-
-            input
-        };
+        let mut input = String::new();
+        let bytes_read = io::stdin().read_line(&mut input).unwrap();
 
-        stored_message = None;
+        if bytes_read == 0 {
+            break;
+        }
 
         let commands = input.split_whitespace().collect::<Vec<_>>();
 
         let cmd = *commands.first().unwrap_or(&"oops");
         match cmd {
             "isready" => println!("readyok"),
-            "setoption" => setoption(
-                &commands,
-                &mut params,
-                &mut report_moves,
-                &mut tree,
-                &mut threads,
-                &mut move_overhead,
-                &mut hash_mb,
-                &mut uci_opponent_rating,
-                &mut uci_rating_adv,
-                &mut contempt_override,
-                &mut contempt_analysis,
-            ),
-            "position" => position(commands, &mut pos),
-            "go" => {
-                // increment game ply every time `go` is called
-                root_game_ply += 2;
-
-                go(
-                    &commands,
-                    &mut tree,
-                    &pos,
-                    root_game_ply,
-                    &params,
-                    report_moves,
-                    policy,
-                    value,
-                    threads,
-                    move_overhead,
-                    contempt_analysis,
-                    &mut stored_message,
-                    #[cfg(feature = "datagen")]
-                    1.0,
-                );
+            "stop" => {
+                engine.stop();
+            }
+            "setoption" => {
+                if let Some((name, value)) = parse_name_value(&commands) {
+                    engine.set_option(&name, value.as_deref());
+                }
             }
+            "position" => {
+                let (fen, moves) = parse_position(&commands);
+                engine.set_position(&fen, &moves);
+            }
+            "go" => engine.start_search(parse_go(&commands)),
             "bench" => {
                 let depth = if let Some(d) = commands.get(1) {
                     d.parse().unwrap_or(ChessState::BENCH_DEPTH)
@@ -90,12 +49,19 @@ pub fn run(policy: &PolicyNetwork, value: &ValueNetwork, tcec_mode: bool) {
                     ChessState::BENCH_DEPTH
                 };
 
-                bench(depth, policy, value, &params);
+                bench(depth, policy, value, engine.params());
+            }
+            "perft" => run_perft(&commands, engine.position()),
+            "quit" => {
+                // quitting mid-search does not wait for it to finish
+                if !engine.is_searching() {
+                    engine.save_experience();
+                }
+
+                std::process::exit(0);
             }
-            "perft" => run_perft(&commands, &pos),
-            "quit" => std::process::exit(0),
             "eval" => {
-                let breakdown = pos.eval_with_contempt(value, &params, pos.stm());
+                let breakdown = engine.eval();
                 println!("cp: {}", breakdown.cp);
                 println!(
                     "wdl raw: {:.2}% {:.2}% {:.2}%",
@@ -117,39 +83,19 @@ pub fn run(policy: &PolicyNetwork, value: &ValueNetwork, tcec_mode: bool) {
                 );
             }
             "policy" => {
-                let mut max = f32::NEG_INFINITY;
-                let mut moves = Vec::new();
-
-                pos.map_moves_with_policies(policy, |mov, policy| {
-                    let s = pos.conv_mov_to_str(mov);
-
-                    if policy > max {
-                        max = policy;
-                    }
-
-                    moves.push((s, policy));
-                });
-
-                let mut total = 0.0;
-
-                for (_, p) in &mut moves {
-                    *p = (*p - max).exp();
-                    total += *p;
-                }
-
-                // Sort the moves by probability in descending order.
-                moves.sort_by(|(_, p1), (_, p2)| p2.partial_cmp(p1).unwrap());
-
-                for (s, p) in moves {
-                    println!("{s} -> {:.2}%", p / total * 100.0);
+                for (s, p) in engine.policy() {
+                    println!("{s} -> {:.2}%", p * 100.0);
                 }
             }
-            "d" => pos.display(policy),
-            "params" => params.list_spsa(),
+            "d" => engine.position().display(policy),
+            "params" => engine.params().list_spsa(),
             "uci" => preamble(tcec_mode),
-            "ucinewgame" => {
-                root_game_ply = 0;
-                tree.clear(threads);
+            "ucinewgame" => engine.new_game(),
+            "epd" => epd::run(&commands[1..], policy, value),
+            "xboard" => {
+                drop(engine);
+                xboard::run(policy, value);
+                return;
             }
             _ => {}
         }
@@ -222,6 +168,10 @@ pub fn bench(depth: usize, policy: &PolicyNetwork, value: &ValueNetwork, params:
         opt_time: None,
         max_depth: depth,
         max_nodes: 1_000_000,
+        gumbel: false,
+        deterministic: false,
+        root_noise: None,
+        move_temperature: None,
         #[cfg(feature = "datagen")]
         kld_min_gain: None,
     };
@@ -232,7 +182,7 @@ pub fn bench(depth: usize, policy: &PolicyNetwork, value: &ValueNetwork, params:
         let abort = AtomicBool::new(false);
         let pos = ChessState::from_fen(fen);
         tree.set_root_position(&pos);
-        let searcher = Searcher::new(&tree, params, policy, value, &abort);
+        let searcher = Searcher::new(&tree, params, policy, value, &abort, None);
         let timer = Instant::now();
         #[cfg(not(feature = "datagen"))]
         searcher.search(1, limits, false, &mut total_nodes);
@@ -248,6 +198,117 @@ pub fn bench(depth: usize, policy: &PolicyNetwork, value: &ValueNetwork, params:
     );
 }
 
+/// Checks that deterministic searches build identical trees when repeated,
+/// single and multi-threaded, with and without sequential halving.
+pub fn determinism(policy: &PolicyNetwork, value: &ValueNetwork) {
+    let fens = [
+        ChessState::STARTPOS,
+        "r3k2r/2pb1ppp/2pp1q2/p7/1nP1B3/1P2P3/P2N1PPP/R2QK2R w KQkq a6 0 14",
+        "8/8/1p2k1p1/3p3p/1p1P1P1P/1P2PK2/8/8 w - - 3 54",
+    ];
+
+    let params = MctsParams::default();
+
+    for fen in fens {
+        for (threads, gumbel) in [(1, false), (4, false), (1, true)] {
+            let limits = Limits {
+                max_time: None,
+                opt_time: None,
+                max_depth: 256,
+                max_nodes: 20_000,
+                gumbel,
+                deterministic: true,
+                root_noise: None,
+                move_temperature: None,
+                #[cfg(feature = "datagen")]
+                kld_min_gain: None,
+            };
+
+            let run = || {
+                let abort = AtomicBool::new(false);
+                let mut tree = Tree::new_mb(8, threads);
+                tree.set_root_position(&ChessState::from_fen(fen));
+                let searcher = Searcher::new(&tree, &params, policy, value, &abort, None);
+                let mut nodes = 0;
+                #[cfg(not(feature = "datagen"))]
+                let mov = searcher.search(threads, limits, false, &mut nodes).0;
+                #[cfg(feature = "datagen")]
+                let mov = searcher
+                    .search(threads, limits, false, &mut nodes, false, 1.0)
+                    .0;
+                (tree.checksum(), mov)
+            };
+
+            let (first, second) = (run(), run());
+
+            assert_eq!(
+                first, second,
+                "{fen}: threads {threads} gumbel {gumbel} is not deterministic"
+            );
+        }
+    }
+
+    println!("Determinism: ok");
+}
+
+/// Generates endgame tablebases into `dir`, up to `men` men.
+pub fn tbgen(dir: Option<String>, men: Option<String>) {
+    let dir = dir.unwrap_or_else(|| "tablebases".to_string());
+    let men = men.and_then(|men| men.parse().ok()).unwrap_or(MAX_MEN);
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+    match Tablebases::generate(&dir, men, threads) {
+        Ok(tbs) => println!("{} tablebases in {dir}", tbs.len()),
+        Err(err) => println!("failed to generate tablebases: {err}"),
+    }
+}
+
+/// Searches for a coordinator on another process, which connects to
+/// `addr` once its `Workers` option lists it.
+pub fn worker(
+    addr: Option<String>,
+    threads: Option<String>,
+    hash_mb: Option<String>,
+    policy: &PolicyNetwork,
+    value: &ValueNetwork,
+) {
+    let addr = addr.unwrap_or_else(|| "127.0.0.1:7878".to_string());
+    let threads = threads
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(1usize)
+        .max(1);
+    let hash_mb = hash_mb
+        .and_then(|h| h.parse().ok())
+        .unwrap_or(64usize)
+        .max(1);
+
+    if let Err(err) = distributed::serve(&addr, policy, value, threads, hash_mb) {
+        println!("worker failed: {err}");
+    }
+}
+
+pub fn serve(
+    addr: Option<String>,
+    threads: Option<String>,
+    hash_mb: Option<String>,
+    policy: &'static PolicyNetwork,
+    value: &'static ValueNetwork,
+) {
+    let addr = addr.unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let threads = threads
+        .and_then(|t| t.parse().ok())
+        .unwrap_or(1usize)
+        .max(1);
+    let hash_mb = hash_mb
+        .and_then(|h| h.parse().ok())
+        .unwrap_or(64usize)
+        .max(1);
+
+    if let Err(err) = serve::run(&addr, policy, value, threads, hash_mb) {
+        println!("serve failed: {err}");
+    }
+}
+
 fn preamble(tcec_mode: bool) {
     println!("id name {}", env!("FORMATTED_NAME"));
     println!("id author Jamie Whiting, Viren & The Monty Authors");
@@ -255,7 +316,24 @@ fn preamble(tcec_mode: bool) {
     println!("option name Threads type spin default 1 min 1 max 512");
     println!("option name UCI_Chess960 type check default false");
     println!("option name Contempt_Analysis type check default false");
+    println!("option name Contempt_Analysis_Both type check default false");
     println!("option name MoveOverhead type spin default 400 min 0 max 5000");
+    println!("option name GumbelRoot type check default false");
+    println!("option name Deterministic type check default false");
+    println!("option name MoveTemperature type spin default 0 min 0 max 500");
+    println!("option name TemperatureDecayPlies type spin default 0 min 0 max 1000");
+    println!("option name RootNoise type spin default 0 min 0 max 100");
+    println!("option name Seed type spin default 0 min 0 max 2147483647");
+    println!("option name UCI_LimitStrength type check default false");
+    println!("option name UCI_Elo type spin default {MAX_ELO} min {MIN_ELO} max {MAX_ELO}");
+    println!("option name RootParallel type spin default 1 min 1 max 64");
+    println!("option name Workers type string default <empty>");
+    println!("option name TablebasePath type string default <empty>");
+    println!("option name Experience type check default false");
+    println!("option name ExperienceFile type string default monty.exp");
+    println!("option name ExperienceDepth type spin default 8 min 0 max 64");
+    println!("option name ExperienceMinVisits type spin default 1000 min 1 max 100000000");
+    println!("option name JsonOutput type check default false");
     println!("option name report_moves type button");
     println!("option name report_iters type button");
     if tcec_mode {
@@ -263,6 +341,8 @@ fn preamble(tcec_mode: bool) {
         println!("option name UCI_RatingAdv type spin default 0");
     }
     println!("option name Contempt type spin default 0 min -1000 max 1000");
+    println!("option name DynamicContempt type check default false");
+    println!("option name DrawScore type spin default 0 min -100 max 100");
 
     #[cfg(feature = "tunable")]
     MctsParams::info(MctsParams::default());
@@ -270,108 +350,6 @@ fn preamble(tcec_mode: bool) {
     println!("uciok");
 }
 
-fn setoption(
-    commands: &[&str],
-    params: &mut MctsParams,
-    report_moves: &mut bool,
-    tree: &mut Tree,
-    threads: &mut usize,
-    move_overhead: &mut usize,
-    hash_mb: &mut usize,
-    uci_opponent_rating: &mut Option<i32>,
-    uci_rating_adv: &mut Option<i32>,
-    contempt_override: &mut Option<i32>,
-    disable_tree_reuse: &mut bool,
-) {
-    let Some((name, value)) = parse_name_value(commands) else {
-        return;
-    };
-
-    match name.as_str() {
-        "report_moves" => {
-            *report_moves = !*report_moves;
-        }
-        "report_iters" => {
-            REPORT_ITERS.fetch_xor(true, Ordering::Relaxed);
-        }
-        "UCI_Chess960" => {}
-        "Contempt_Analysis" => {
-            if let Some(v) = value {
-                *disable_tree_reuse = v.eq_ignore_ascii_case("true");
-            }
-        }
-        "Threads" => {
-            if let Some(v) = value {
-                if let Ok(parsed) = v.parse::<usize>() {
-                    *threads = parsed.max(1);
-                    let root = tree.root_position().clone();
-                    tree.rebuild(*hash_mb, *threads, root);
-                }
-            }
-        }
-        "MoveOverhead" => {
-            if let Some(v) = value {
-                if let Ok(parsed) = v.parse::<usize>() {
-                    *move_overhead = parsed;
-                }
-            }
-        }
-        "Hash" => {
-            if let Some(v) = value {
-                if let Ok(parsed) = v.parse::<i32>() {
-                    *hash_mb = parsed.max(1) as usize;
-                    let root = tree.root_position().clone();
-                    tree.rebuild(*hash_mb, *threads, root);
-                }
-            }
-        }
-        "Contempt" => {
-            if let Some(v) = value {
-                if let Ok(parsed) = v.parse::<i32>() {
-                    let clamped = parsed.clamp(-1000, 1000);
-                    *contempt_override = Some(clamped);
-                    params.set("contempt", clamped);
-                    println!("info string using contempt {} elo", clamped);
-                }
-            }
-        }
-        "UCI_Opponent" => {
-            if contempt_override.is_some() || uci_rating_adv.is_some() {
-                return;
-            }
-
-            if let Some(v) = value {
-                if let Ok(parsed) = parse_uci_opponent_rating(&v) {
-                    *uci_opponent_rating = parsed;
-                    apply_uci_contempt(params, *uci_opponent_rating, *uci_rating_adv);
-                }
-            }
-        }
-        "UCI_RatingAdv" => {
-            if contempt_override.is_some() {
-                return;
-            }
-
-            if let Some(v) = value {
-                if v.eq_ignore_ascii_case("none") {
-                    *uci_rating_adv = None;
-                    apply_uci_contempt(params, *uci_opponent_rating, *uci_rating_adv);
-                } else if let Ok(parsed) = v.parse::<f32>() {
-                    let rating_adv = parsed.round() as i32;
-                    *uci_rating_adv = Some(rating_adv);
-                    apply_uci_contempt(params, *uci_opponent_rating, *uci_rating_adv);
-                }
-            }
-        }
-        _ => {
-            if let Some(v) = value {
-                let parsed = v.parse::<i32>().unwrap_or(0);
-                params.set(&name, parsed);
-            }
-        }
-    }
-}
-
 fn parse_name_value(commands: &[&str]) -> Option<(String, Option<String>)> {
     if commands.len() < 3 || commands[1] != "name" {
         return None;
@@ -403,40 +381,29 @@ fn parse_name_value(commands: &[&str]) -> Option<(String, Option<String>)> {
     Some((name, value))
 }
 
-fn parse_uci_opponent_rating(value: &str) -> Result<Option<i32>, ()> {
-    let mut parts = value.split_whitespace();
-    // format: "none <rating|none> <type> <name>"
-    parts.next().ok_or(())?; // skip first field (unused)
-    let rating_str = parts.next().ok_or(())?;
-    if rating_str.eq_ignore_ascii_case("none") {
-        return Ok(None);
-    }
+pub(crate) fn position(commands: Vec<&str>, pos: &mut ChessState) {
+    let (fen, moves) = parse_position(&commands);
 
-    rating_str.parse::<i32>().map(Some).map_err(|_| ())
-}
-
-fn apply_uci_contempt(
-    params: &mut MctsParams,
-    opponent_rating: Option<i32>,
-    rating_adv: Option<i32>,
-) {
-    const DEFAULT_SELF_RATING: i32 = 3520;
-
-    let contempt = rating_adv.or_else(|| opponent_rating.map(|opp| DEFAULT_SELF_RATING - opp));
+    *pos = ChessState::from_fen(&fen);
 
-    if let Some(contempt) = contempt {
-        let clamped = contempt.clamp(-1000, 1000);
-        params.set("contempt", clamped);
-        println!("info string using contempt {} elo", clamped);
+    for mov in moves {
+        pos.make_uci_move(mov);
     }
 }
 
-fn position(commands: Vec<&str>, pos: &mut ChessState) {
+/// Like [`position`], but rejects invalid FENs and illegal moves.
+pub(crate) fn checked_position(commands: &[&str]) -> Result<ChessState, String> {
+    let (fen, moves) = parse_position(commands);
+    ChessState::from_fen_and_moves(&fen, &moves)
+}
+
+/// Splits a `position` command into a FEN and the moves played from it.
+fn parse_position<'a>(commands: &[&'a str]) -> (String, Vec<&'a str>) {
     let mut fen = String::new();
     let mut move_list = Vec::new();
     let mut moves = false;
 
-    for cmd in commands {
+    for &cmd in commands {
         match cmd {
             "position" | "fen" => {}
             "startpos" => fen = ChessState::STARTPOS.to_string(),
@@ -451,46 +418,11 @@ fn position(commands: Vec<&str>, pos: &mut ChessState) {
         }
     }
 
-    *pos = ChessState::from_fen(&fen);
-
-    for &m in move_list.iter() {
-        let mut this_mov = Move::default();
-
-        pos.map_legal_moves(|mov| {
-            if m == pos.conv_mov_to_str(mov) {
-                this_mov = mov;
-            }
-        });
-
-        pos.make_move(this_mov);
-    }
+    (fen, move_list)
 }
 
-#[allow(clippy::too_many_arguments)]
-fn go(
-    commands: &[&str],
-    tree: &mut Tree,
-    pos: &ChessState,
-    root_game_ply: u32,
-    params: &MctsParams,
-    report_moves: bool,
-    policy: &PolicyNetwork,
-    value: &ValueNetwork,
-    threads: usize,
-    move_overhead: usize,
-    disable_tree_reuse: bool,
-    stored_message: &mut Option<String>,
-    #[cfg(feature = "datagen")] temp: f32,
-) {
-    let mut max_nodes = usize::MAX;
-    let mut max_time = None;
-    let mut max_depth = 256;
-
-    let mut times = [None; 2];
-    let mut incs = [None; 2];
-    let mut movestogo = None;
-    let mut opt_time = None;
-
+pub(crate) fn parse_go(commands: &[&str]) -> GoOptions {
+    let mut options = GoOptions::default();
     let mut mode = "";
 
     let saturating_parse = |s: &str| s.parse::<i64>().ok().map(|val| val.max(0) as u64);
@@ -506,78 +438,142 @@ fn go(
             "binc" => mode = "binc",
             "movestogo" => mode = "movestogo",
             _ => match mode {
-                "nodes" => max_nodes = cmd.parse().unwrap_or(max_nodes),
-                "movetime" => max_time = cmd.parse().ok(),
-                "depth" => max_depth = cmd.parse().unwrap_or(max_depth),
-                "wtime" => times[0] = saturating_parse(cmd),
-                "btime" => times[1] = saturating_parse(cmd),
-                "winc" => incs[0] = saturating_parse(cmd),
-                "binc" => incs[1] = saturating_parse(cmd),
-                "movestogo" => movestogo = saturating_parse(cmd),
+                "nodes" => options.nodes = cmd.parse().ok().or(options.nodes),
+                "movetime" => options.movetime = cmd.parse().ok(),
+                "depth" => options.depth = cmd.parse().ok().or(options.depth),
+                "wtime" => options.times[0] = saturating_parse(cmd),
+                "btime" => options.times[1] = saturating_parse(cmd),
+                "winc" => options.incs[0] = saturating_parse(cmd),
+                "binc" => options.incs[1] = saturating_parse(cmd),
+                "movestogo" => options.movestogo = saturating_parse(cmd),
                 _ => mode = "none",
             },
         }
     }
 
-    // `go wtime <wtime> btime <btime> winc <winc> binc <binc>``
-    if let Some(mut remaining) = times[pos.stm()] {
-        // apply move overhead
-        remaining = remaining.saturating_sub(move_overhead as u64).max(10);
+    options
+}
 
-        let timeman =
-            SearchHelpers::get_time(remaining, incs[pos.stm()], root_game_ply, movestogo, params);
+/// Plays every pair of neighbouring `UCI_Elo` levels against each other
+/// and compares the measured Elo difference to the intended one. Games
+/// start from fixed openings with both colours, use fixed seeds and run
+/// single threaded with node limits, so results are reproducible.
+///
+/// Finishes with the settings of every level and its Elo measured down
+/// from `MAX_ELO`, in the format of the anchors in `mcts::strength`.
+pub fn calibrate_strength(games: Option<String>, policy: &PolicyNetwork, value: &ValueNetwork) {
+    const STEP: i32 = 200;
+    const OPENINGS: [&str; 8] = [
+        "e2e4 e7e5 g1f3 b8c6",
+        "d2d4 d7d5 c2c4 e7e6",
+        "e2e4 c7c5 g1f3 d7d6",
+        "d2d4 g8f6 c2c4 g7g6",
+        "c2c4 e7e5 b1c3 g8f6",
+        "e2e4 e7e6 d2d4 d7d5",
+        "g1f3 d7d5 g2g3 g8f6",
+        "e2e4 c7c6 d2d4 d7d5",
+    ];
 
-        opt_time = Some(timeman.0);
-        max_time = Some(timeman.1);
-    }
+    let games = games.and_then(|g| g.parse().ok()).unwrap_or(64usize).max(2);
+    let mut differences = Vec::new();
+
+    for elo in (MIN_ELO..MAX_ELO).step_by(STEP as usize) {
+        let weak = Strength::from_elo(elo);
+        let strong = Strength::from_elo(elo + STEP);
+        let mut score = 0.0;
+
+        for game in 0..games {
+            let opening = OPENINGS[game / 2 % OPENINGS.len()];
+
+            score += if game % 2 == 0 {
+                play_calibration_game(opening, [strong, weak], game as u64, policy, value)
+            } else {
+                1.0 - play_calibration_game(opening, [weak, strong], game as u64, policy, value)
+            };
+        }
+
+        let fraction = (score / games as f32).clamp(0.001, 0.999);
+        let measured = -400.0 * (1.0 / fraction - 1.0).log10();
+
+        println!(
+            "UCI_Elo {} vs {elo}: {score:.1}/{games}, measured {measured:+.0} elo, intended {STEP:+}",
+            elo + STEP,
+        );
 
-    // `go movetime <time>`
-    if let Some(max) = max_time {
-        // if both movetime and increment time controls given, use
-        max_time = Some(max_time.unwrap_or(u128::MAX).min(max));
+        differences.push(measured);
     }
 
-    let abort = AtomicBool::new(false);
+    let mut measured = MAX_ELO as f32;
 
-    if disable_tree_reuse {
-        tree.clear(threads);
+    for (i, difference) in differences.iter().rev().chain([&0.0]).enumerate() {
+        let level = Strength::from_elo(MAX_ELO - i as i32 * STEP);
+
+        println!(
+            "({measured:.0}, {}.0, {:.1}, {:.1}),",
+            level.max_nodes, level.pst_scale, level.temperature,
+        );
+
+        measured -= difference;
     }
+}
+
+/// Score of white, where `players` are indexed by side.
+fn play_calibration_game(
+    opening: &str,
+    players: [Strength; 2],
+    seed: u64,
+    policy: &PolicyNetwork,
+    value: &ValueNetwork,
+) -> f32 {
+    const MAX_PLIES: u64 = 400;
 
-    tree.set_root_position(pos);
+    let mut pos = ChessState::default();
+    let commands = format!("position startpos moves {opening}");
+    position(commands.split_whitespace().collect(), &mut pos);
 
-    let limits = Limits {
-        max_time,
-        opt_time,
-        max_depth,
-        max_nodes,
+    let mut trees = [Tree::new_mb(8, 1), Tree::new_mb(8, 1)];
+
+    for ply in 0..MAX_PLIES {
+        let stm = pos.stm();
+
+        match pos.game_state() {
+            GameState::Ongoing => {}
+            GameState::Draw => return 0.5,
+            GameState::Lost(_) => return if stm == 0 { 0.0 } else { 1.0 },
+            GameState::Won(_) => return if stm == 0 { 1.0 } else { 0.0 },
+        }
+
+        let mut limits = Limits {
+            max_time: None,
+            opt_time: None,
+            max_depth: 256,
+            max_nodes: usize::MAX,
+            gumbel: false,
+            deterministic: false,
+            root_noise: None,
+            move_temperature: None,
+            #[cfg(feature = "datagen")]
+            kld_min_gain: None,
+        };
+
+        let mut params = MctsParams::default();
+        players[stm].apply(&mut limits, &mut params, seed * MAX_PLIES + ply);
+
+        let tree = &mut trees[stm];
+        tree.set_root_position(&pos);
+
+        let abort = AtomicBool::new(false);
+        let searcher = Searcher::new(tree, &params, policy, value, &abort, None);
+
+        #[cfg(not(feature = "datagen"))]
+        let mov = searcher.search(1, limits, false, &mut 0).0;
         #[cfg(feature = "datagen")]
-        kld_min_gain: None,
-    };
+        let mov = searcher.search(1, limits, false, &mut 0, false, 0.0).0;
 
-    std::thread::scope(|s| {
-        s.spawn(|| {
-            let searcher = Searcher::new(tree, params, policy, value, &abort);
-            let mov = searcher
-                .search(
-                    threads,
-                    limits,
-                    true,
-                    &mut 0,
-                    #[cfg(feature = "datagen")]
-                    false,
-                    #[cfg(feature = "datagen")]
-                    temp,
-                )
-                .0;
-            println!("bestmove {}", pos.conv_mov_to_str(mov));
-
-            if report_moves {
-                searcher.display_moves();
-            }
-        });
+        pos.make_move(mov);
+    }
 
-        *stored_message = handle_search_input(&abort);
-    });
+    0.5
 }
 
 fn run_perft(commands: &[&str], pos: &ChessState) {
@@ -592,24 +588,3 @@ fn run_perft(commands: &[&str], pos: &ChessState) {
         count as f32 / time as f32
     );
 }
-
-fn handle_search_input(abort: &AtomicBool) -> Option<String> {
-    loop {
-        let mut input = String::new();
-        let bytes_read = io::stdin().read_line(&mut input).unwrap();
-
-        if bytes_read == 0 {
-            process::exit(0);
-        }
-
-        match input.as_str().trim() {
-            "isready" => println!("readyok"),
-            "quit" => std::process::exit(0),
-            "stop" => {
-                abort.store(true, Ordering::Relaxed);
-                return None;
-            }
-            _ => return Some(input),
-        };
-    }
-}
@@ -0,0 +1,488 @@
+use crate::{
+    chess::{ChessState, GameState},
+    engine::{Engine, GoOptions},
+    mcts::{Score, SearchEvent},
+    networks::{PolicyNetwork, ValueNetwork},
+};
+
+use montyformat::chess::Flag;
+
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+};
+
+// Standard input is read on its own thread, so that a finished search
+// can play its move while waiting for the next command.
+enum Input {
+    Line(String),
+    BestMove(String),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Idle,
+    Thinking,
+    Analysing,
+}
+
+/// Time control set by `level`, in milliseconds.
+struct Level {
+    moves_per_session: u64,
+    base: u64,
+    inc: u64,
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        // the default of xboard itself, `level 40 5 0`
+        Self {
+            moves_per_session: 40,
+            base: 300_000,
+            inc: 0,
+        }
+    }
+}
+
+struct Xboard {
+    engine: Engine,
+    fen: String,
+    moves: Vec<String>,
+    pos: ChessState,
+    // side played by the engine, or `None` in force mode
+    engine_side: Option<usize>,
+    mode: Mode,
+    searching: bool,
+    // searches stopped without playing their move, whose
+    // best moves are still to come through the channel
+    abandoned: usize,
+    chess960: bool,
+    level: Level,
+    move_time: Option<u128>,
+    depth: Option<usize>,
+    time: Option<u64>,
+    otim: Option<u64>,
+    post: Arc<AtomicBool>,
+}
+
+/// Runs the engine over the Chess Engine Communication Protocol used by
+/// xboard and WinBoard, until `quit` or the end of input.
+pub fn run(policy: &'static PolicyNetwork, value: &'static ValueNetwork) {
+    let (sender, receiver) = mpsc::channel();
+    let post = Arc::new(AtomicBool::new(false));
+
+    let reporter_post = post.clone();
+    let reporter_sender = sender.clone();
+    let engine = Engine::new(policy, value, move |event| match event {
+        SearchEvent::Info(info) if reporter_post.load(Ordering::Relaxed) => {
+            let depth = info.depth.map_or(info.pv.len(), |(depth, _)| depth);
+
+            // mate scores are given as 100000 + moves to mate
+            let score = match info.score {
+                Score::Cp(cp) => cp,
+                Score::Mate(moves) if moves > 0 => 100000 + moves,
+                Score::Mate(moves) => -100000 + moves,
+            };
+
+            println!(
+                "{depth} {score} {} {} {}",
+                info.time / 10,
+                info.nodes,
+                info.pv.join(" ")
+            );
+        }
+        SearchEvent::Info(_) => {}
+        SearchEvent::Message(message) => println!("# {message}"),
+        SearchEvent::BestMove(mov) => {
+            let _ = reporter_sender.send(Input::BestMove(mov));
+        }
+    });
+
+    thread::spawn(move || loop {
+        let mut input = String::new();
+
+        if io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+            let _ = sender.send(Input::Line("quit".to_string()));
+            break;
+        }
+
+        if sender.send(Input::Line(input)).is_err() {
+            break;
+        }
+    });
+
+    let mut xboard = Xboard {
+        engine,
+        fen: ChessState::STARTPOS.to_string(),
+        moves: Vec::new(),
+        pos: ChessState::default(),
+        engine_side: Some(1),
+        mode: Mode::Idle,
+        searching: false,
+        abandoned: 0,
+        chess960: false,
+        level: Level::default(),
+        move_time: None,
+        depth: None,
+        time: None,
+        otim: None,
+        post,
+    };
+
+    for input in receiver {
+        match input {
+            Input::BestMove(mov) => xboard.best_move(&mov),
+            Input::Line(line) => {
+                let commands = line.split_whitespace().collect::<Vec<_>>();
+                xboard.command(&commands);
+            }
+        }
+    }
+}
+
+impl Xboard {
+    fn command(&mut self, commands: &[&str]) {
+        let arg = |i: usize| commands.get(i).copied().unwrap_or_default();
+        let centiseconds = |s: &str| s.parse::<i64>().ok().map(|cs| cs.max(0) as u64 * 10);
+
+        match arg(0) {
+            "protover" => println!(
+                "feature myname=\"{}\" setboard=1 usermove=1 ping=1 playother=1 analyze=1 \
+                 colors=0 sigint=0 sigterm=0 reuse=1 san=0 time=1 draw=0 memory=1 smp=1 \
+                 variants=\"normal,fischerandom\" done=1",
+                env!("FORMATTED_NAME")
+            ),
+            "new" => {
+                self.halt();
+                self.engine.new_game();
+                self.fen = ChessState::STARTPOS.to_string();
+                self.moves.clear();
+                self.engine_side = Some(1);
+                self.chess960 = false;
+                self.depth = None;
+                self.time = None;
+                self.otim = None;
+                self.sync_position();
+            }
+            "variant" => {
+                self.chess960 = arg(1) == "fischerandom";
+                let fen = self.fen.clone();
+                self.set_board(&fen);
+            }
+            "force" => {
+                self.halt();
+                self.engine_side = None;
+            }
+            "go" => {
+                self.halt();
+                self.engine_side = Some(self.pos.stm());
+                self.think();
+            }
+            "playother" => {
+                self.halt();
+                self.engine_side = Some(self.pos.stm() ^ 1);
+            }
+            "level" => {
+                // `level <moves per session> <minutes[:seconds]> <increment seconds>`
+                let base = arg(2).split_once(':').map_or_else(
+                    || arg(2).parse::<f64>().ok().map(|min| min * 60.0),
+                    |(min, sec)| Some(min.parse::<f64>().ok()? * 60.0 + sec.parse::<f64>().ok()?),
+                );
+
+                if let (Ok(moves_per_session), Some(base), Ok(inc)) =
+                    (arg(1).parse(), base, arg(3).parse::<f64>())
+                {
+                    self.level = Level {
+                        moves_per_session,
+                        base: (base * 1000.0).max(0.0) as u64,
+                        inc: (inc * 1000.0).max(0.0) as u64,
+                    };
+                    self.move_time = None;
+                }
+            }
+            "st" => {
+                if let Ok(seconds) = arg(1).parse::<f64>() {
+                    self.move_time = Some((seconds * 1000.0).max(0.0) as u128);
+                }
+            }
+            "sd" => self.depth = arg(1).parse().ok(),
+            "time" => self.time = centiseconds(arg(1)),
+            "otim" => self.otim = centiseconds(arg(1)),
+            "usermove" => self.user_move(arg(1)),
+            "?" if self.mode == Mode::Thinking => {
+                self.engine.stop();
+            }
+            "ping" => println!("pong {}", arg(1)),
+            "setboard" => {
+                self.halt();
+                self.set_board(&commands[1..].join(" "));
+                self.restart_analysis();
+            }
+            "undo" | "remove" => {
+                self.halt();
+
+                let plies = if arg(0) == "undo" { 1 } else { 2 };
+                let len = self.moves.len().saturating_sub(plies);
+                self.moves.truncate(len);
+                self.sync_position();
+                self.restart_analysis();
+            }
+            "analyze" => {
+                self.halt();
+                self.engine_side = None;
+                self.mode = Mode::Analysing;
+                self.restart_analysis();
+            }
+            "exit" => {
+                self.halt();
+                self.mode = Mode::Idle;
+            }
+            "result" => {
+                self.halt();
+                self.engine_side = None;
+            }
+            "post" => self.post.store(true, Ordering::Relaxed),
+            "nopost" => self.post.store(false, Ordering::Relaxed),
+            "memory" => self.engine.set_option("Hash", Some(arg(1))),
+            "cores" => self.engine.set_option("Threads", Some(arg(1))),
+            "quit" => {
+                // quitting mid-search does not wait for it to finish
+                if !self.engine.is_searching() {
+                    self.engine.save_experience();
+                }
+
+                std::process::exit(0);
+            }
+            // moves are also accepted without `usermove`
+            mov if self.parse_move(mov).is_some() => self.user_move(mov),
+            _ => {}
+        }
+    }
+
+    /// Stops the running search, if any, without playing its move.
+    fn halt(&mut self) {
+        if self.searching {
+            self.engine.stop();
+            self.searching = false;
+            self.abandoned += 1;
+        }
+
+        if self.mode == Mode::Thinking {
+            self.mode = Mode::Idle;
+        }
+    }
+
+    fn best_move(&mut self, mov: &str) {
+        if self.abandoned > 0 {
+            self.abandoned -= 1;
+            return;
+        }
+
+        self.searching = false;
+
+        if self.mode != Mode::Thinking {
+            return;
+        }
+
+        self.mode = Mode::Idle;
+        println!("move {}", self.move_to_xboard(mov));
+        self.moves.push(mov.to_string());
+        self.sync_position();
+    }
+
+    fn user_move(&mut self, mov: &str) {
+        let Some(mov) = self.parse_move(mov) else {
+            println!("Illegal move: {mov}");
+            return;
+        };
+
+        self.halt();
+        self.moves.push(mov);
+        self.sync_position();
+
+        if self.mode == Mode::Analysing {
+            self.restart_analysis();
+        } else if self.engine_side == Some(self.pos.stm()) {
+            self.think();
+        }
+    }
+
+    fn set_board(&mut self, fen: &str) {
+        let fen = if self.chess960 {
+            shredder_castling(fen)
+        } else {
+            fen.to_string()
+        };
+
+        if !ChessState::is_valid_fen(&fen) {
+            println!("tellusererror Illegal position");
+            return;
+        }
+
+        self.fen = fen;
+        self.moves.clear();
+        self.sync_position();
+    }
+
+    fn sync_position(&mut self) {
+        let moves = self.moves.iter().map(String::as_str).collect::<Vec<_>>();
+        self.engine.set_position(&self.fen, &moves);
+        self.pos = self.engine.position().clone();
+    }
+
+    fn restart_analysis(&mut self) {
+        if self.mode == Mode::Analysing && self.pos.game_state() == GameState::Ongoing {
+            self.searching = true;
+            self.engine.start_search(GoOptions::default());
+        }
+    }
+
+    fn think(&mut self) {
+        if self.pos.game_state() != GameState::Ongoing {
+            return;
+        }
+
+        let stm = self.pos.stm();
+        let mut options = GoOptions {
+            depth: self.depth,
+            ..GoOptions::default()
+        };
+
+        if let Some(move_time) = self.move_time {
+            options.movetime = Some(move_time);
+        } else {
+            options.times[stm] = Some(self.time.unwrap_or(self.level.base));
+            options.times[stm ^ 1] = Some(self.otim.unwrap_or(self.level.base));
+            options.incs = [Some(self.level.inc); 2];
+
+            if self.level.moves_per_session > 0 {
+                let played = self.pos.board().fullm().saturating_sub(1);
+                let session = self.level.moves_per_session;
+                options.movestogo = Some(session - u64::from(played) % session);
+            }
+        }
+
+        self.mode = Mode::Thinking;
+        self.searching = true;
+        self.engine.start_search(options);
+    }
+
+    /// Converts a move from the GUI into the notation of the engine.
+    fn parse_move(&self, mov: &str) -> Option<String> {
+        let castle = match mov {
+            "O-O" | "0-0" => Some(Flag::KS),
+            "O-O-O" | "0-0-0" => Some(Flag::QS),
+            _ => None,
+        };
+
+        let mut found = None;
+
+        self.pos.map_legal_moves(|legal| {
+            let uci = self.pos.conv_mov_to_str(legal);
+
+            if castle.map_or(uci == mov, |flag| legal.flag() == flag) {
+                found = Some(uci);
+            }
+        });
+
+        found
+    }
+
+    /// Converts a move from the engine into the notation of the GUI,
+    /// which writes castling as `O-O` or `O-O-O` in Fischer random.
+    fn move_to_xboard(&self, mov: &str) -> String {
+        let mut castle = None;
+
+        if self.chess960 {
+            self.pos.map_legal_moves(|legal| {
+                if self.pos.conv_mov_to_str(legal) == mov {
+                    castle = match legal.flag() {
+                        Flag::KS => Some("O-O"),
+                        Flag::QS => Some("O-O-O"),
+                        _ => None,
+                    };
+                }
+            });
+        }
+
+        castle.map_or_else(|| mov.to_string(), str::to_string)
+    }
+}
+
+/// Rewrites `KQkq` castling rights as the files of the outermost rooks,
+/// which is how the engine tells Fischer random positions apart.
+fn shredder_castling(fen: &str) -> String {
+    let mut fields = fen
+        .split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    let Some(ranks) = fields
+        .first()
+        .map(|board| board.split('/').collect::<Vec<_>>())
+    else {
+        return fen.to_string();
+    };
+
+    if ranks.len() != 8 || fields.len() < 3 {
+        return fen.to_string();
+    }
+
+    let expand = |rank: &str| {
+        let mut squares = Vec::new();
+
+        for ch in rank.chars() {
+            match ch.to_digit(10) {
+                Some(empty) => squares.extend(std::iter::repeat_n('.', empty as usize)),
+                None => squares.push(ch),
+            }
+        }
+
+        squares
+    };
+
+    // first and eighth ranks, from the a-file
+    let back_ranks = [expand(ranks[7]), expand(ranks[0])];
+
+    let rook_file = |side: usize, kingside: bool| {
+        let (king, rook) = [('K', 'R'), ('k', 'r')][side];
+        let rank = &back_ranks[side];
+        let king_file = rank.iter().position(|&ch| ch == king)?;
+
+        let file = if kingside {
+            (king_file + 1..rank.len()).rev().find(|&f| rank[f] == rook)
+        } else {
+            (0..king_file).find(|&f| rank[f] == rook)
+        }?;
+
+        let letter = b"ABCDEFGH"[file] as char;
+        Some(if side == 0 {
+            letter
+        } else {
+            letter.to_ascii_lowercase()
+        })
+    };
+
+    let rights = fields[2]
+        .chars()
+        .filter_map(|ch| match ch {
+            'K' => rook_file(0, true),
+            'Q' => rook_file(0, false),
+            'k' => rook_file(1, true),
+            'q' => rook_file(1, false),
+            _ => Some(ch),
+        })
+        .collect::<String>();
+
+    fields[2] = if rights.is_empty() {
+        "-".to_string()
+    } else {
+        rights
+    };
+
+    fields.join(" ")
+}